@@ -0,0 +1,102 @@
+use std::future::Future;
+use log::debug;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use zeroize::Zeroize;
+use crate::cli::Platform;
+use crate::error::{InventoryError, LoginFailure};
+use crate::model::{AbsoluteInventoryPath, AuthorizationInfo, DirectoryMetadata, LoginInfo, LoginOptions, Record, RecordId, RecordOwner};
+
+mod api;
+mod cassette;
+mod http;
+mod in_memory;
+
+pub use cassette::CassetteMode;
+pub use http::{HttpClient, HttpClientConfig, RetryPolicy};
+pub use api::ApiBackend;
+pub use in_memory::InMemoryBackend;
+#[cfg(test)]
+pub use in_memory::fixture;
+
+/// Primitive operations against an inventory storage.
+///
+/// Every operation receives the session explicitly, so that an implementor does not have to
+/// track login state by itself.
+pub trait InventoryBackend: Send + Sync {
+    /// Creates new session.
+    fn login(&self, login_info: LoginInfo, options: &LoginOptions) -> impl Future<Output = Result<AuthorizationInfo, InventoryError>> + Send;
+
+    /// Revokes the session.
+    fn logout(&self, authorization_info: &AuthorizationInfo) -> impl Future<Output = Result<(), InventoryError>> + Send;
+
+    /// Extends the lifetime of the session. The token is not changed.
+    fn extend_session(&self, authorization_info: &AuthorizationInfo) -> impl Future<Output = Result<(), InventoryError>> + Send;
+
+    /// Returns records that are placed immediately under `path`.
+    fn get_directory_items(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> impl Future<Output = Result<Vec<Record>, InventoryError>> + Send;
+
+    /// Returns the directory itself that is pointed by `path`.
+    fn get_directory_metadata(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> impl Future<Output = Result<DirectoryMetadata, InventoryError>> + Send;
+
+    fn get_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> impl Future<Output = Result<Record, InventoryError>> + Send;
+
+    /// Inserts or overwrites `record`. Its id is taken from `record.id`.
+    fn put_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record: &Record) -> impl Future<Output = Result<(), InventoryError>> + Send;
+
+    fn delete_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> impl Future<Output = Result<(), InventoryError>> + Send;
+}
+
+fn authorize(request: RequestBuilder, authorization_info: Option<&AuthorizationInfo>, platform: Platform) -> RequestBuilder {
+    if let Some(authorization_info) = authorization_info {
+        request.header(AUTHORIZATION, authorization_info.as_authorization_header_value(platform))
+    } else {
+        request
+    }
+}
+
+/// `normalize` is applied before deserialization, in order to absorb platform differences.
+async fn read_json<T: DeserializeOwned>(res: Response, normalize: impl FnOnce(&mut Value) + Send) -> Result<T, InventoryError> {
+    let raw = res.text().await?;
+    debug!("raw output: {raw}");
+
+    let mut value = serde_json::from_str(&raw)?;
+    normalize(&mut value);
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Turns the response of login into [`InventoryError::Login`] if it is refused by 400, 401 or 403.
+/// The service tells the reason only by plain text body, such as `Invalid credentials` or `TOTP`.
+async fn check_login_status(res: Response) -> Result<Response, InventoryError> {
+    let status = res.status();
+    // others, such as 404 from wrong endpoint or 429 from rate limit, are not about the credential
+    if !matches!(status, StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        return HttpClient::check_status(res)
+    }
+
+    let body = res.text().await?;
+    debug!("login refused ({status}): {body}");
+    let body = body.to_lowercase();
+
+    let reason = if body.contains("totp") {
+        LoginFailure::TotpRequired
+    } else if body.contains("lock") || body.contains("block") {
+        LoginFailure::Locked
+    } else {
+        LoginFailure::BadCredentials
+    };
+
+    Err(InventoryError::Login(reason))
+}
+
+/// Same as [`read_json`], but the body is not logged because it contains credentials.
+async fn read_secret_json<T: DeserializeOwned>(res: Response) -> Result<T, InventoryError> {
+    let mut raw = res.text().await?;
+    let parsed = serde_json::from_str(&raw);
+    raw.zeroize();
+
+    Ok(parsed?)
+}
@@ -0,0 +1,174 @@
+use log::debug;
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::backend::{authorize, check_login_status, read_json, read_secret_json, HttpClient, InventoryBackend};
+use crate::cli::Platform;
+use crate::error::InventoryError;
+use crate::model::{AbsoluteInventoryPath, AuthorizationInfo, DirectoryMetadata, LoginInfo, LoginOptions, Record, RecordId, RecordOwner, ResoniteUserLoginPostBody, ResoniteUserLoginPostResponse, UserLoginPostBody, UserLoginPostResponse, flatten_resonite_version, nest_resonite_version};
+
+/// Talks with the official API of `platform`. Both platforms share the endpoints, and differ only in
+/// the login and the representation of versions, which are handled by the private methods below.
+pub struct ApiBackend {
+    platform: Platform,
+    http: HttpClient,
+    base_point: String,
+}
+
+impl ApiBackend {
+    /// `base_point` must not end with `/`.
+    pub const fn new(platform: Platform, http: HttpClient, base_point: String) -> Self {
+        Self {
+            platform,
+            http,
+            base_point,
+        }
+    }
+
+    fn login_body(&self, req: RequestBuilder, login_info: LoginInfo, options: &LoginOptions) -> RequestBuilder {
+        match self.platform {
+            Platform::Neos => req.json(&UserLoginPostBody::create(login_info, options)),
+            Platform::Resonite => {
+                let body = ResoniteUserLoginPostBody::create(login_info, options)
+                    .expect("Resonite requires credentials to create session");
+
+                // Resonite refuses to create session without this header. It identifies the machine as
+                // the official client does, so it must not change between logins.
+                let uid = format!("{:X}", Sha256::digest(options.machine_id.expose_secret().as_bytes()));
+                req.header("UID", uid)
+                    .json(&body)
+            }
+        }
+    }
+
+    async fn read_login_response(&self, res: Response) -> Result<AuthorizationInfo, InventoryError> {
+        match self.platform {
+            Platform::Neos => {
+                let res: UserLoginPostResponse = read_secret_json(res).await?;
+                Ok(res.to_authorization_info())
+            }
+            Platform::Resonite => {
+                let res: ResoniteUserLoginPostResponse = read_secret_json(res).await?;
+                Ok(res.entity.to_authorization_info())
+            }
+        }
+    }
+
+    /// Reads a record, or something in the same shape such as [`DirectoryMetadata`].
+    async fn read_record<T: DeserializeOwned>(&self, res: Response) -> Result<T, InventoryError> {
+        let platform = self.platform;
+        read_json(res, |record| if platform == Platform::Resonite {
+            flatten_resonite_version(record);
+        }).await
+    }
+
+    async fn read_records(&self, res: Response) -> Result<Vec<Record>, InventoryError> {
+        let platform = self.platform;
+        read_json(res, |records| if let (Platform::Resonite, Value::Array(records)) = (platform, records) {
+            records.iter_mut().for_each(flatten_resonite_version);
+        }).await
+    }
+
+    fn record_body(&self, record: &Record) -> Value {
+        let mut body = serde_json::to_value(record).expect("Record must be serializable");
+        if self.platform == Platform::Resonite {
+            nest_resonite_version(&mut body);
+        }
+
+        body
+    }
+}
+
+impl InventoryBackend for ApiBackend {
+    async fn login(&self, login_info: LoginInfo, options: &LoginOptions) -> Result<AuthorizationInfo, InventoryError> {
+        let base_point = &self.base_point;
+        let mut req = self.http
+            .post(format!("{base_point}/userSessions"));
+
+        if let Some(x) = login_info.get_totp() {
+            req = req.header("TOTP", x.0.clone());
+        }
+
+        let req = self.login_body(req, login_info, options);
+
+        debug!("post 2");
+        let res = check_login_status(self.http.send_unchecked(req).await?).await?;
+        self.read_login_response(res).await
+    }
+
+    async fn logout(&self, authorization_info: &AuthorizationInfo) -> Result<(), InventoryError> {
+        let base_point = &self.base_point;
+        let owner_id = &authorization_info.owner_id;
+        let req = self.http
+            .delete(format!("{base_point}/userSessions/{owner_id}/{auth_token}", auth_token = authorization_info.token.expose_secret()));
+
+        self.http.send(authorize(req, Some(authorization_info), self.platform)).await?;
+        Ok(())
+    }
+
+    async fn extend_session(&self, authorization_info: &AuthorizationInfo) -> Result<(), InventoryError> {
+        let base_point = &self.base_point;
+        let req = self.http
+            .patch(format!("{base_point}/userSessions"));
+
+        self.http.send(authorize(req, Some(authorization_info), self.platform)).await?;
+        Ok(())
+    }
+
+    async fn get_directory_items(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> Result<Vec<Record>, InventoryError> {
+        let base_point = &self.base_point;
+        let path = path.to_uri_query_value();
+        // NOTE:
+        // https://api.neos.com/api/users/U-kisaragi-marine/records/root/Inventory/Test <-- これはディレクトリのメタデータを単体で返す
+        let endpoint = format!("{base_point}/{owner_path}/records?path={path}", owner_path = owner.api_path());
+
+        debug!("endpoint: {endpoint}", endpoint = &endpoint);
+        let res = self.http.send(authorize(self.http.get(endpoint), authorization_info, self.platform)).await?;
+
+        self.read_records(res).await
+    }
+
+    async fn get_directory_metadata(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> Result<DirectoryMetadata, InventoryError> {
+        let base_point = &self.base_point;
+        let path = path.to_absolute_path();
+        let endpoint = format!("{base_point}/{owner_path}/records/root/{path}", owner_path = owner.api_path());
+
+        debug!("endpoint: {endpoint}", endpoint = &endpoint);
+        let res = self.http.send(authorize(self.http.get(endpoint), authorization_info, self.platform)).await?;
+
+        self.read_record(res).await
+    }
+
+    async fn get_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> Result<Record, InventoryError> {
+        let base_point = &self.base_point;
+        let endpoint = format!("{base_point}/{owner_path}/records/{record_id}", owner_path = owner.api_path());
+
+        let res = self.http.send(authorize(self.http.get(endpoint), authorization_info, self.platform)).await?;
+
+        self.read_record(res).await
+    }
+
+    async fn put_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record: &Record) -> Result<(), InventoryError> {
+        let base_point = &self.base_point;
+        let endpoint = format!("{base_point}/{owner_path}/records/{record_id}", owner_path = owner.api_path(), record_id = &record.id);
+        debug!("endpoint: {endpoint}", endpoint = &endpoint);
+
+        let req = authorize(self.http.put(endpoint), authorization_info, self.platform)
+            .json(&self.record_body(record));
+
+        let res = self.http.send(req).await?;
+        debug!("Response: {res:?}", res = &res);
+        Ok(())
+    }
+
+    async fn delete_record(&self, authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> Result<(), InventoryError> {
+        let base_point = &self.base_point;
+        let endpoint = format!("{base_point}/{owner_path}/records/{record_id}", owner_path = owner.api_path());
+
+        let deleted = self.http.send(authorize(self.http.delete(endpoint), authorization_info, self.platform)).await?;
+
+        debug!("deleted: {deleted:?}");
+        Ok(())
+    }
+}
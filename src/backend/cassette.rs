@@ -0,0 +1,371 @@
+//! Record and replay of HTTP exchanges, for reproducible bug reports.
+//!
+//! A cassette is a directory. Each exchange is saved as `NNNNNN.json`, in the order it was made.
+//! Credentials (`Authorization` and `TOTP` headers, `password` and `token` in bodies, and session token in URL)
+//! and machine identifiers (`UID` header and `secretMachineId` in bodies) are redacted before saved.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use log::{debug, error};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::{Request, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+use crate::error::InventoryError;
+
+// must be URL-safe: replayed token is embedded in the URL as is
+const REDACTED: &str = "REDACTED";
+const SECRET_HEADERS: [&str; 3] = ["authorization", "totp", "uid"];
+const SECRET_KEYS: [&str; 4] = ["password", "token", "secretMachineId", "totp"];
+
+#[derive(Debug, Clone)]
+pub enum CassetteMode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+pub enum Cassette {
+    Record {
+        directory: PathBuf,
+        sequence: AtomicUsize,
+    },
+    Replay {
+        /// `None` means already replayed.
+        interactions: Mutex<Vec<Option<Interaction>>>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<RecordedBody>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Option<RecordedBody>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RecordedBody {
+    Json(Value),
+    Text(String),
+}
+
+impl Cassette {
+    pub fn open(mode: CassetteMode) -> Result<Self> {
+        match mode {
+            CassetteMode::Record(directory) => {
+                fs::create_dir_all(&directory)
+                    .with_context(|| format!("failed to create {directory}", directory = directory.display()))?;
+
+                Ok(Self::Record { directory, sequence: AtomicUsize::new(0) })
+            }
+            CassetteMode::Replay(directory) => {
+                let interactions = load_interactions(&directory)?;
+                debug!("{count} interaction(s) are loaded", count = interactions.len());
+
+                Ok(Self::Replay { interactions: Mutex::new(interactions.into_iter().map(Some).collect()) })
+            }
+        }
+    }
+
+    /// Returns `None` if this cassette is not for replay.
+    pub fn replay(&self, request: &Request) -> Option<Result<Response, InventoryError>> {
+        let Self::Replay { interactions } = self else {
+            return None
+        };
+
+        let recorded_request = RecordedRequest::capture(request);
+        let mut interactions = interactions.lock().expect("poisoned");
+        let found = interactions
+            .iter_mut()
+            .find(|interaction| interaction.as_ref().is_some_and(|i| i.request.matches(&recorded_request)))
+            .and_then(Option::take);
+        drop(interactions);
+
+        let res = found
+            .map(|interaction| interaction.response.restore(request.url().clone()))
+            .ok_or_else(|| InventoryError::Replay {
+                resource: format!("{method} {url}", method = recorded_request.method, url = recorded_request.url),
+            });
+
+        Some(res)
+    }
+
+    /// Saves the exchange if this cassette is for recording. `res` is buffered and restored in any case.
+    pub async fn record(&self, request: &Request, res: Response) -> Result<Response, InventoryError> {
+        let Self::Record { directory, sequence } = self else {
+            return Ok(res)
+        };
+
+        let url = res.url().clone();
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+
+        let interaction = Interaction {
+            request: RecordedRequest::capture(request),
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: capture_headers(&headers),
+                body: RecordedBody::capture(&body),
+            },
+        };
+
+        let path = directory.join(format!("{seq:06}.json", seq = sequence.fetch_add(1, Ordering::SeqCst)));
+        let written = serde_json::to_string_pretty(&interaction)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(&path, json).map_err(anyhow::Error::from));
+        if let Err(e) = written {
+            error!("failed to record HTTP exchange into {path}: {e}", path = path.display());
+        }
+
+        // the recorded one is redacted, so restore from the original
+        let mut restored = http::Response::builder()
+            .status(status)
+            .url(url)
+            .body(body)
+            .expect("response must be valid");
+        *restored.headers_mut() = headers;
+
+        Ok(restored.into())
+    }
+}
+
+fn load_interactions(directory: &Path) -> Result<Vec<Interaction>> {
+    let mut paths = fs::read_dir(directory)
+        .with_context(|| format!("failed to read {directory}", directory = directory.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "json"));
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let content = fs::read_to_string(path)?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("failed to parse {path}", path = path.display()))
+        })
+        .collect()
+}
+
+impl RecordedRequest {
+    fn capture(request: &Request) -> Self {
+        let mut url = request.url().to_string();
+        // the session token appears in the URL on logout
+        if let Some(token) = request.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit_once(':'))
+            .map(|(_, token)| token.trim()) {
+            if !token.is_empty() {
+                url = url.replace(token, REDACTED);
+            }
+        }
+
+        Self {
+            method: request.method().to_string(),
+            url,
+            headers: capture_headers(request.headers()),
+            body: request.body().and_then(reqwest::Body::as_bytes).and_then(RecordedBody::capture),
+        }
+    }
+
+    /// Headers are not compared, because they are redacted.
+    fn matches(&self, other: &Self) -> bool {
+        self.method == other.method && self.url == other.url
+    }
+}
+
+impl RecordedResponse {
+    fn restore(self, url: Url) -> Response {
+        let builder = self.headers.iter().fold(
+            http::Response::builder().status(self.status).url(url),
+            |builder, (name, value)| builder.header(name, value),
+        );
+
+        let body = match self.body {
+            None => Vec::new(),
+            Some(RecordedBody::Json(value)) => serde_json::to_vec(&value).expect("Value must be serializable"),
+            Some(RecordedBody::Text(text)) => text.into_bytes(),
+        };
+
+        builder
+            .body(body)
+            .expect("recorded response must be valid")
+            .into()
+    }
+}
+
+impl RecordedBody {
+    fn capture(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() {
+            return None
+        }
+
+        let body = serde_json::from_slice(bytes).map_or_else(
+            |_| Self::Text(String::from_utf8_lossy(bytes).into_owned()),
+            |mut value| {
+                redact_json(&mut value);
+                Self::Json(value)
+            },
+        );
+
+        Some(body)
+    }
+}
+
+fn capture_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    if !value.is_null() {
+                        *value = Value::String(REDACTED.to_string());
+                    }
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use reqwest::header::AUTHORIZATION;
+    use reqwest::{Client, Request};
+    use crate::cli::OneTimePassword;
+    use crate::model::{LoginInfo, LoginOptions, Password, ResoniteUserLoginPostBody, Secret, UserIdentifyPointer, UserLoginPostBody};
+    use super::{Cassette, Interaction, RecordedBody, RecordedRequest, RecordedResponse};
+
+    const SECRETS: [&str; 4] = ["hunter2", "machine-secret", "123456", "session-token"];
+
+    fn login_info() -> LoginInfo {
+        LoginInfo::ByPassword {
+            user_identify_pointer: UserIdentifyPointer::user_id("U-test".parse().unwrap()),
+            password: Password::new("hunter2".to_string()),
+            totp: Some(OneTimePassword("123456".to_string())),
+        }
+    }
+
+    fn options() -> LoginOptions {
+        LoginOptions {
+            machine_id: Secret::new("machine-secret".to_string()),
+            remember_me: false,
+        }
+    }
+
+    fn assert_redacted(captured: &RecordedRequest) {
+        let json = serde_json::to_string(captured).unwrap();
+        for secret in SECRETS {
+            assert!(!json.contains(secret), "{secret} survived in {json}");
+        }
+    }
+
+    fn logout(token: &str) -> Request {
+        Client::new()
+            .delete(format!("http://127.0.0.1/userSessions/U-test/{token}"))
+            .header(AUTHORIZATION, format!("neos U-test:{token}"))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn capture_redacts_neos_login() {
+        let request = Client::new()
+            .post("http://127.0.0.1/userSessions")
+            .header("TOTP", "123456")
+            .json(&UserLoginPostBody::create(login_info(), &options()))
+            .build()
+            .unwrap();
+
+        let captured = RecordedRequest::capture(&request);
+        assert_redacted(&captured);
+        assert!(captured.headers.iter().any(|(name, value)| name == "totp" && value == "REDACTED"));
+    }
+
+    #[test]
+    fn capture_redacts_resonite_login() {
+        let request = Client::new()
+            .post("http://127.0.0.1/userSessions")
+            .header("TOTP", "123456")
+            .header("UID", "0123456789ABCDEF")
+            .json(&ResoniteUserLoginPostBody::create(login_info(), &options()).unwrap())
+            .build()
+            .unwrap();
+
+        let captured = RecordedRequest::capture(&request);
+        assert_redacted(&captured);
+        assert!(captured.headers.iter().any(|(name, value)| name == "uid" && value == "REDACTED"));
+    }
+
+    #[test]
+    fn capture_redacts_token_in_logout() {
+        let captured = RecordedRequest::capture(&logout("session-token"));
+
+        assert_redacted(&captured);
+        assert_eq!(captured.url, "http://127.0.0.1/userSessions/U-test/REDACTED");
+        assert!(captured.headers.iter().any(|(name, value)| name == "authorization" && value == "REDACTED"));
+    }
+
+    #[test]
+    fn capture_redacts_token_in_response() {
+        let Some(RecordedBody::Json(body)) = RecordedBody::capture(br#"{"userId":"U-test","token":"session-token"}"#) else {
+            panic!("JSON body must be captured as JSON")
+        };
+
+        assert_eq!(body["token"], "REDACTED");
+        assert_eq!(body["userId"], "U-test");
+    }
+
+    #[test]
+    fn replay_matches_redacted_logout() {
+        let cassette = Cassette::Replay {
+            interactions: Mutex::new(vec![Some(Interaction {
+                request: RecordedRequest::capture(&logout("recorded-token")),
+                response: RecordedResponse { status: 200, headers: vec![], body: None },
+            })]),
+        };
+
+        // the token differs from the recorded one, as a new session is created on replay
+        let res = cassette.replay(&logout("replayed-token")).unwrap().unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(cassette.replay(&logout("replayed-token")).unwrap().is_err(), "each interaction must be replayed only once");
+    }
+}
@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use rand::Rng;
+use reqwest::{Client, ClientBuilder, IntoUrl, Method, Proxy, Request, RequestBuilder, Response, ResponseBuilderExt, StatusCode};
+use reqwest::header::RETRY_AFTER;
+use url::Url;
+use crate::backend::cassette::{Cassette, CassetteMode};
+use crate::error::InventoryError;
+
+/// How transient failures are retried. Only idempotent requests are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 0 disables retry.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    /// Also bounds `Retry-After` from the server. Requests which are told to wait longer are not retried.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with full jitter: random duration between 0 and `base_delay * 2^retry_count`,
+    /// capped by `max_delay`.
+    fn backoff(&self, retry_count: u32) -> Duration {
+        let ceil = self.base_delay
+            .saturating_mul(2_u32.saturating_pow(retry_count))
+            .min(self.max_delay);
+
+        ceil.mul_f64(rand::rng().random_range(0.0..=1.0))
+    }
+}
+
+/// Shared HTTP client. Every request to the API must go through this.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    retry_policy: RetryPolicy,
+    cassette: Option<Arc<Cassette>>,
+}
+
+/// Settings of the underlying connection.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    /// Applies to each read of the response, not to the whole request.
+    pub read_timeout: Duration,
+    pub proxy: Option<Url>,
+    /// PEM file which contains additional trusted root certificate(s).
+    pub ca_certificate: Option<PathBuf>,
+    pub retry_policy: RetryPolicy,
+    pub cassette: Option<CassetteMode>,
+}
+
+impl HttpClient {
+    const USER_AGENT: &'static str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    pub fn new(config: HttpClientConfig) -> Result<Self> {
+        let c = ClientBuilder::new()
+            .user_agent(Self::USER_AGENT)
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout);
+
+        #[cfg(feature = "https_os_native")]
+        let c = c.use_native_tls();
+
+        let c = match config.proxy {
+            Some(proxy) => c.proxy(Proxy::all(proxy)?),
+            None => c,
+        };
+
+        let c = match config.ca_certificate {
+            Some(path) => add_root_certificates(c, &path)?,
+            None => c,
+        };
+
+        Ok(Self {
+            client: c
+                .build()
+                .context("failed to initialize HTTP client")?,
+            retry_policy: config.retry_policy,
+            cassette: config.cassette.map(Cassette::open).transpose()?.map(Arc::new),
+        })
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    pub fn patch(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.patch(url)
+    }
+
+    /// Sends `request`, and turns unsuccessful status into [`InventoryError`].
+    /// Idempotent requests are retried on connection failure, 429 and 5xx according to [`RetryPolicy`].
+    pub async fn send(&self, request: RequestBuilder) -> std::result::Result<Response, InventoryError> {
+        let res = self.send_unchecked(request).await?;
+        Self::check_status(res)
+    }
+
+    /// Same as [`Self::send`], but returns the response as is even if its status is unsuccessful.
+    pub async fn send_unchecked(&self, request: RequestBuilder) -> std::result::Result<Response, InventoryError> {
+        let request = request.build()?;
+
+        let res = if let Some(cassette) = &self.cassette {
+            if let Some(replayed) = cassette.replay(&request) {
+                replayed?
+            } else {
+                let recorded_request = request.try_clone();
+                let res = self.execute(request).await?;
+                if let Some(recorded_request) = recorded_request {
+                    cassette.record(&recorded_request, res).await?
+                } else {
+                    res
+                }
+            }
+        } else {
+            self.execute(request).await?
+        };
+
+        Ok(res)
+    }
+
+    pub fn check_status(res: Response) -> std::result::Result<Response, InventoryError> {
+        let status = res.status();
+        let resource = masked_url(res.url()).to_string();
+        debug!("{resource}: {status}");
+
+        match status {
+            s if s.is_success() => Ok(res),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(InventoryError::Auth { resource, status }),
+            StatusCode::NOT_FOUND => Err(InventoryError::NotFound { resource }),
+            status => Err(InventoryError::HttpStatus { resource, status }),
+        }
+    }
+
+    async fn execute(&self, request: Request) -> reqwest::Result<Response> {
+        let retryable = request.method().is_idempotent();
+        let is_delete = request.method() == Method::DELETE;
+        let mut retry_count = 0;
+
+        let res = loop {
+            let attempt = if retryable { request.try_clone() } else { None };
+            let Some(attempt) = attempt.filter(|_| retry_count < self.retry_policy.max_retries) else {
+                // last attempt
+                break self.client.execute(request).await;
+            };
+
+            let delay = match self.client.execute(attempt).await {
+                Ok(res) if !is_transient_status(res.status()) => break Ok(res),
+                Ok(res) => {
+                    let delay = match retry_after(&res) {
+                        Some(delay) if delay > self.retry_policy.max_delay => {
+                            warn!("{url}: {status}, giving up because the server asks to wait for {delay:?}", url = masked_url(res.url()), status = res.status());
+                            break Ok(res);
+                        }
+                        Some(delay) => delay,
+                        None => self.retry_policy.backoff(retry_count),
+                    };
+                    warn!("{url}: {status}, retrying in {delay:?}", url = masked_url(res.url()), status = res.status());
+                    delay
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    let e = masked_error(e);
+                    let delay = self.retry_policy.backoff(retry_count);
+                    warn!("{e}, retrying in {delay:?}");
+                    delay
+                }
+                Err(e) => break Err(e),
+            };
+
+            tokio::time::sleep(delay).await;
+            retry_count += 1;
+        };
+
+        match res {
+            // the former attempt may have reached the server even if its response did not come back
+            Ok(res) if is_delete && retry_count > 0 && res.status() == StatusCode::NOT_FOUND => {
+                warn!("{url}: {status} on retry, assuming it was deleted by the former attempt", url = masked_url(res.url()), status = res.status());
+                Ok(deleted(res.url().clone()))
+            }
+            res => res.map_err(masked_error),
+        }
+    }
+}
+
+/// The session token is a part of URL on logout, so it must be masked before the URL is logged or
+/// carried by an error.
+fn masked_url(url: &Url) -> Url {
+    let is_session = url.path_segments()
+        .is_some_and(|segments| matches!(segments.rev().nth(2), Some("userSessions")));
+
+    let mut url = url.clone();
+    if is_session {
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop().push("REDACTED");
+        }
+    }
+
+    url
+}
+
+fn masked_error(e: reqwest::Error) -> reqwest::Error {
+    match e.url().map(masked_url) {
+        Some(url) => e.with_url(url),
+        None => e,
+    }
+}
+
+/// Successful response to DELETE, which carries nothing.
+fn deleted(url: Url) -> Response {
+    http::Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .url(url)
+        .body(Vec::new())
+        .expect("response must be valid")
+        .into()
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads `Retry-After` header, which is either delay in seconds or HTTP-date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds))
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(feature = "https_os_native")]
+fn add_root_certificates(c: ClientBuilder, path: &Path) -> Result<ClientBuilder> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("failed to read CA certificate {path}", path = path.display()))?;
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("failed to parse CA certificate {path}", path = path.display()))?;
+
+    Ok(certificates.into_iter().fold(c, ClientBuilder::add_root_certificate))
+}
+
+#[cfg(not(feature = "https_os_native"))]
+fn add_root_certificates(_c: ClientBuilder, _path: &Path) -> Result<ClientBuilder> {
+    unreachable!("Arguments validation must be done at this point")
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use log::warn;
+use reqwest::StatusCode;
+use uuid::Uuid;
+use crate::backend::InventoryBackend;
+use crate::error::InventoryError;
+use crate::model::{AbsoluteInventoryPath, AuthorizationInfo, DirectoryMetadata, LoginInfo, LoginOptions, Record, RecordId, RecordOwner, SessionToken, UserIdentifyPointer};
+
+/// Keeps every record on memory. Sessions are not checked at all, so anyone can see and modify anything.
+pub struct InMemoryBackend {
+    records: RwLock<HashMap<RecordOwner, Vec<Record>>>,
+}
+
+impl InMemoryBackend {
+    pub const fn new(records: HashMap<RecordOwner, Vec<Record>>) -> Self {
+        Self {
+            records: RwLock::new(records),
+        }
+    }
+
+    /// Each record is put into the inventory of [`Record::owner_id`]. Records without it are skipped.
+    pub fn from_records(records: Vec<Record>) -> Self {
+        let mut inventories: HashMap<RecordOwner, Vec<Record>> = HashMap::new();
+        for record in records {
+            if let Some(owner) = &record.owner_id {
+                inventories.entry(owner.clone()).or_default().push(record);
+            } else {
+                warn!("{id} is skipped because it does not have owner", id = &record.id);
+            }
+        }
+
+        Self::new(inventories)
+    }
+
+    fn find_record(&self, owner: &RecordOwner, predicate: impl Fn(&Record) -> bool) -> Option<Record> {
+        self.records
+            .read()
+            .expect("poisoned")
+            .get(owner)
+            .and_then(|records| records.iter().find(|record| predicate(record)).cloned())
+    }
+}
+
+impl InventoryBackend for InMemoryBackend {
+    async fn login(&self, login_info: LoginInfo, _options: &LoginOptions) -> Result<AuthorizationInfo, InventoryError> {
+        let LoginInfo::ByPassword { user_identify_pointer: UserIdentifyPointer::UserId { user_id }, .. } = login_info else {
+            return Err(InventoryError::Auth {
+                resource: "in-memory backend can identify user only by user id".to_string(),
+                status: StatusCode::FORBIDDEN,
+            })
+        };
+
+        Ok(AuthorizationInfo::new(user_id, SessionToken::new(Uuid::new_v4().to_string())))
+    }
+
+    async fn logout(&self, _authorization_info: &AuthorizationInfo) -> Result<(), InventoryError> {
+        Ok(())
+    }
+
+    async fn extend_session(&self, _authorization_info: &AuthorizationInfo) -> Result<(), InventoryError> {
+        Ok(())
+    }
+
+    async fn get_directory_items(&self, _authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> Result<Vec<Record>, InventoryError> {
+        let path = path.to_record_path();
+
+        let records = self.records
+            .read()
+            .expect("poisoned")
+            .get(owner)
+            .map(|records| records.iter().filter(|record| record.path == path).cloned().collect())
+            .unwrap_or_default();
+
+        Ok(records)
+    }
+
+    async fn get_directory_metadata(&self, _authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, path: &AbsoluteInventoryPath) -> Result<DirectoryMetadata, InventoryError> {
+        let not_found = || InventoryError::NotFound { resource: format!("{owner}/{path}") };
+        let (parent, name) = path.split_last().ok_or_else(not_found)?;
+        let parent = parent.to_record_path();
+
+        self.find_record(owner, |record| record.path == parent && record.name == name)
+            .as_ref()
+            .and_then(DirectoryMetadata::from_directory_record)
+            .ok_or_else(not_found)
+    }
+
+    async fn get_record(&self, _authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> Result<Record, InventoryError> {
+        self.find_record(owner, |record| &record.id == record_id)
+            .ok_or_else(|| InventoryError::NotFound { resource: format!("{owner}/{record_id}") })
+    }
+
+    async fn put_record(&self, _authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record: &Record) -> Result<(), InventoryError> {
+        let mut records = self.records.write().expect("poisoned");
+        let owned_records = records.entry(owner.clone()).or_default();
+
+        if let Some(existing) = owned_records.iter_mut().find(|existing| existing.id == record.id) {
+            *existing = record.clone();
+        } else {
+            owned_records.push(record.clone());
+        }
+        drop(records);
+
+        Ok(())
+    }
+
+    async fn delete_record(&self, _authorization_info: Option<&AuthorizationInfo>, owner: &RecordOwner, record_id: &RecordId) -> Result<(), InventoryError> {
+        let not_found = || InventoryError::NotFound { resource: format!("{owner}/{record_id}") };
+        let mut records = self.records.write().expect("poisoned");
+        let owned_records = records.get_mut(owner).ok_or_else(not_found)?;
+
+        let before = owned_records.len();
+        owned_records.retain(|record| &record.id != record_id);
+        let after = owned_records.len();
+        drop(records);
+
+        if after == before {
+            Err(not_found())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Records to build [`InMemoryBackend`] in tests.
+#[cfg(test)]
+pub mod fixture {
+    use serde_json::json;
+    use crate::model::{AbsoluteInventoryPath, Record, RecordOwner, RecordType};
+
+    /// `id` is either `U-` or `G-` prefixed.
+    pub fn owner(id: &str) -> RecordOwner {
+        id.parse().expect("fixture must be valid")
+    }
+
+    /// `path` is separated by `/`, such as `Inventory/Work`.
+    pub fn path(path: &str) -> AbsoluteInventoryPath {
+        let Ok(path) = path.parse();
+        path
+    }
+
+    /// `path` is in the form of [`Record::path`], such as `Inventory\Work`.
+    pub fn record(owner: &str, id: &str, path: &str, name: &str, record_type: RecordType) -> Record {
+        serde_json::from_value(json!({
+            "id": id,
+            "globalVersion": 1,
+            "localVersion": 1,
+            "lastModifyingUserId": owner,
+            "lastModifyingMachineId": "machine",
+            "name": name,
+            "recordType": record_type.to_string(),
+            "ownerName": owner,
+            "path": path,
+            "isPublic": false,
+            "isForPatrons": false,
+            "isListed": false,
+            "isDeleted": false,
+            "creationTime": "2024-01-01T00:00:00Z",
+            "lastModificationTime": "2024-01-01T00:00:00Z",
+            "randomOrder": 0,
+            "visits": 0,
+            "rating": 0.0,
+            "ownerId": owner,
+        })).expect("fixture must be valid")
+    }
+
+    /// Link to `target`, such as `neosrec:///U-example/R-xxx`.
+    pub fn link(owner: &str, id: &str, path: &str, name: &str, target: &str) -> Record {
+        Record {
+            asset_uri: Some(target.parse().expect("fixture must be valid")),
+            ..record(owner, id, path, name, RecordType::Link)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::InventoryBackend;
+    use crate::error::InventoryError;
+    use crate::model::{Record, RecordId, RecordType};
+    use crate::operation::PreLogin;
+    use super::fixture::{owner, path, record};
+    use super::InMemoryBackend;
+
+    fn backend() -> InMemoryBackend {
+        InMemoryBackend::from_records(vec![
+            record("U-test", "R-work", "Inventory", "Work", RecordType::Directory),
+            record("U-test", "R-chair", "Inventory", "Chair", RecordType::Object),
+            record("U-test", "R-desk", "Inventory", "Desk", RecordType::Object),
+        ])
+    }
+
+    fn ids(records: &[Record]) -> Vec<&str> {
+        records.iter().map(|record| record.id.0.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn move_records_keeping_record_id() {
+        let client = PreLogin::from_session_data(backend(), None, None);
+
+        client.move_records(owner("U-test"), vec![RecordId("R-chair".to_string())], vec!["Inventory".to_string(), "Work".to_string()], true).await.unwrap();
+
+        let moved = client.get_directory_items(owner("U-test"), path("Inventory/Work")).await.unwrap();
+        assert_eq!(ids(&moved), ["R-chair"]);
+        assert_eq!(moved[0].path, "Inventory\\Work");
+        assert_eq!(moved[0].name, "Chair");
+
+        let left = client.get_directory_items(owner("U-test"), path("Inventory")).await.unwrap();
+        assert_eq!(ids(&left), ["R-work", "R-desk"]);
+    }
+
+    #[tokio::test]
+    async fn move_records_with_new_record_id() {
+        let client = PreLogin::from_session_data(backend(), None, None);
+
+        client.move_records(owner("U-test"), vec![RecordId("R-chair".to_string())], vec!["Inventory".to_string(), "Work".to_string()], false).await.unwrap();
+
+        let moved = client.get_directory_items(owner("U-test"), path("Inventory/Work")).await.unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_ne!(moved[0].id.0, "R-chair");
+        assert!(moved[0].id.0.starts_with("R-"));
+        assert_eq!(moved[0].name, "Chair");
+
+        let old = client.get_record(owner("U-test"), RecordId("R-chair".to_string())).await;
+        assert!(matches!(old, Err(InventoryError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn move_records_refuses_directory() {
+        let client = PreLogin::from_session_data(backend(), None, None);
+
+        let result = client.move_records(owner("U-test"), vec![RecordId("R-work".to_string())], vec!["Inventory".to_string(), "Elsewhere".to_string()], true).await;
+        assert!(matches!(result, Err(InventoryError::DirectoryMove { .. })));
+
+        let left = client.get_directory_items(owner("U-test"), path("Inventory")).await.unwrap();
+        assert_eq!(ids(&left), ["R-work", "R-chair", "R-desk"]);
+    }
+
+    #[tokio::test]
+    async fn get_directory_metadata() {
+        let backend = backend();
+
+        let metadata = backend.get_directory_metadata(None, &owner("U-test"), &path("Inventory/Work")).await.unwrap();
+        let metadata = serde_json::to_value(metadata).unwrap();
+        assert_eq!(metadata["id"], "R-work");
+        assert_eq!(metadata["name"], "Work");
+        assert_eq!(metadata["path"], "Inventory");
+
+        let not_directory = backend.get_directory_metadata(None, &owner("U-test"), &path("Inventory/Chair")).await;
+        assert!(matches!(not_directory, Err(InventoryError::NotFound { .. })));
+
+        let missing = backend.get_directory_metadata(None, &owner("U-test"), &path("Inventory/Missing")).await;
+        assert!(matches!(missing, Err(InventoryError::NotFound { .. })));
+    }
+}
@@ -1,34 +1,95 @@
-use std::io::IsTerminal;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use clap::{Parser, Subcommand};
 use email_address::EmailAddress;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use fern::colors::ColoredLevelConfig;
 use log::{debug, LevelFilter, warn};
 use derive_more::{Display, FromStr};
 use strum::{EnumString, Display as StrumDisplay};
-use serde::Serialize;
-use crate::model::{AbsoluteInventoryPath, LoginInfo, Password, RecordId, UserId, UserIdentifyPointer};
+use totp_rs::{Algorithm, TOTP};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use zeroize::Zeroize;
+use crate::backend::{CassetteMode, HttpClientConfig, RetryPolicy};
+use crate::config::Config;
+use crate::filter::RecordFilter;
+use crate::output::{FieldSelection, OutputFormat};
+use crate::sort::SortOptions;
+use crate::stats::ReportFormat;
+use crate::model::{AbsoluteInventoryPath, GroupId, LoginInfo, Password, RecordId, RecordOwner, Secret, UserId, UserIdentifyPointer};
 
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Args {
     #[clap(short, long)]
     email: Option<EmailAddress>,
-    #[clap(short, long)]
+    /// Prefer --password-from-stdin or the environment variable, because this is visible from other processes.
+    /// If none of them is given, the password is prompted when stdin is a terminal.
+    #[clap(short, long, env = "REINVENTORY_MANAGER_PASSWORD", hide_env_values = true)]
     password: Option<Password>,
+    /// Reads the password from the first line of stdin.
+    #[clap(long, conflicts_with_all = ["password", "read_token_from_stdin"])]
+    password_from_stdin: bool,
     #[clap(short, long)]
     totp: Option<OneTimePassword>,
+    /// File which contains base32-encoded TOTP secret. The code is generated on login instead of --totp.
+    #[clap(long, conflicts_with = "totp")]
+    totp_secret_file: Option<PathBuf>,
     #[clap(short, long)]
     user_id: Option<UserId>,
     #[clap(long, default_value_t = LogLevel::Warn)]
-    log_level: LogLevel,
+    pub log_level: LogLevel,
     #[clap(long)]
     read_token_from_stdin: bool,
+    /// Requests long-lived session on login. Useful with `login` subcommand.
+    #[clap(long)]
+    remember_me: bool,
     #[clap(long)]
     keep_record_id: bool,
+    /// Number of directories which are fetched at once while walking directories.
+    #[clap(short, long, default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: usize,
     #[clap(short, long = "color", default_value_t = ColorPolicy::Auto)]
     color_policy: ColorPolicy,
     #[clap(long)]
     platform: Option<Platform>,
+    /// Overrides the API endpoint, such as `https://api.neos.com/api`.
+    #[clap(long, env = "REINVENTORY_MANAGER_API_BASE")]
+    api_base: Option<Url>,
+    /// Path to the configuration file. Defaults to `config.toml` in the user configuration directory.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// How many times a failed idempotent request is retried. 0 disables retry.
+    #[clap(long, default_value_t = 3)]
+    max_retries: u32,
+    /// Base delay of exponential backoff, in milliseconds.
+    #[clap(long, default_value_t = 500)]
+    retry_base_delay: u64,
+    /// Upper bound of backoff, in milliseconds. `Retry-After` from the server takes precedence if it is shorter than this.
+    #[clap(long, default_value_t = 30_000)]
+    retry_max_delay: u64,
+    /// Timeout for establishing connection, in seconds. Defaults to 30.
+    #[clap(long)]
+    connect_timeout: Option<u64>,
+    /// Timeout for each read of response, in seconds. Defaults to 60.
+    #[clap(long)]
+    read_timeout: Option<u64>,
+    /// HTTP(S) proxy which every request goes through.
+    #[clap(long)]
+    proxy: Option<Url>,
+    /// PEM file which contains additional trusted root certificate(s). Requires the build with `https_os_native`.
+    #[clap(long)]
+    ca_cert: Option<PathBuf>,
+    /// Saves every HTTP request and response into the directory, with credentials redacted.
+    #[clap(long, conflicts_with = "replay_http")]
+    record_http: Option<PathBuf>,
+    /// Replays HTTP responses from the directory which is made by --record-http, instead of accessing network.
+    #[clap(long)]
+    replay_http: Option<PathBuf>,
     #[clap(subcommand)]
     sub_command: ToolSubCommand,
 }
@@ -41,18 +102,126 @@ pub enum ColorPolicy {
     Never,
 }
 
-#[derive(EnumString, Display, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+impl ColorPolicy {
+    fn should_color(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Auto => std::io::stdout().is_terminal(),
+            Self::Never => false
+        }
+    }
+}
+
+#[derive(EnumString, Display, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub enum Platform {
     Neos,
     Resonite,
 }
 
+impl Platform {
+    pub const fn default_api_base(self) -> &'static str {
+        match self {
+            Self::Neos => "https://api.neos.com/api",
+            Self::Resonite => "https://api.resonite.com",
+        }
+    }
+
+    /// scheme part of `Authorization` header.
+    pub const fn authorization_scheme(self) -> &'static str {
+        match self {
+            Self::Neos => "neos",
+            Self::Resonite => "res",
+        }
+    }
+}
+
 #[derive(Serialize, Display, FromStr, Debug, Eq, PartialEq, Clone)]
 pub struct OneTimePassword(pub String);
 
+impl OneTimePassword {
+    /// Asks the code on the terminal.
+    pub fn prompt() -> Result<Self> {
+        eprint!("Two-factor authentication code: ");
+        std::io::stderr().flush()?;
+
+        let mut buf = String::new();
+        std::io::stdin().read_line(&mut buf).context("failed to read two-factor authentication code")?;
+        let code = buf.trim();
+        if code.is_empty() {
+            bail!("two-factor authentication code is not given");
+        }
+
+        Ok(Self(code.to_string()))
+    }
+
+    /// Generates current code from the secret in `path`, as authenticator apps do (RFC 6238, SHA-1, 6 digits, 30 seconds).
+    pub fn generate_from_secret_file(path: &Path) -> Result<Self> {
+        let content = Secret::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("failed to read TOTP secret file {path}", path = path.display()))?
+        );
+        let secret = decode_totp_secret(content.expose_secret())
+            .with_context(|| format!("TOTP secret in {path} is not valid base32", path = path.display()))?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system clock is before UNIX epoch")?
+            .as_secs();
+
+        Ok(Self::generate(secret, time))
+    }
+
+    /// Generates the code at `time`, which is in seconds since UNIX epoch. `secret` is zeroized after that.
+    fn generate(secret: Vec<u8>, time: u64) -> Self {
+        let mut totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret);
+        let code = totp.generate(time);
+        totp.secret.zeroize();
+
+        Self(code)
+    }
+}
+
+/// Decodes base32-encoded TOTP secret.
+fn decode_totp_secret(content: &str) -> Result<Vec<u8>, totp_rs::SecretParseError> {
+    // authenticator apps show the secret in groups, and some of them pad it
+    let mut encoded = content
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>();
+    encoded.make_ascii_uppercase();
+
+    // totp-rs zeroizes neither of them by itself, unless its `zeroize` feature is enabled
+    let encoded = totp_rs::Secret::Encoded(encoded);
+    let secret = encoded.to_bytes();
+    if let totp_rs::Secret::Encoded(mut encoded) = encoded {
+        encoded.zeroize();
+    }
+
+    secret
+}
+
 impl Args {
     pub fn validate(self) -> Result<AfterArgs> {
-        let login_info = if let Some(password) = self.password {
+        let password = if self.password.is_some() {
+            self.password
+        } else if self.password_from_stdin {
+            let mut buf = String::new();
+            std::io::stdin().read_line(&mut buf).context("failed to read password from stdin")?;
+            let password = Password::new(buf.trim_end_matches(['\r', '\n']).to_string());
+            buf.zeroize();
+            if password.expose_secret().is_empty() {
+                bail!("Please provide password from stdin!");
+            }
+            Some(password)
+        } else if (self.email.is_some() || self.user_id.is_some()) && !self.read_token_from_stdin && std::io::stdin().is_terminal() {
+            let password = rpassword::prompt_password("Password: ").context("failed to read password")?;
+            Some(Password::new(password))
+        } else {
+            None
+        };
+
+        let totp = self.totp;
+
+        let login_info = if let Some(password) = password {
             match (self.email, self.user_id) {
                 (Some(_), Some(_)) => {
                     bail!("You can not provide both --email and --user-id.")
@@ -61,14 +230,14 @@ impl Args {
                     Some(LoginInfo::ByPassword {
                         user_identify_pointer: UserIdentifyPointer::email(email),
                         password,
-                        totp: self.totp
+                        totp,
                     })
                 }
                 (None, Some(user_id)) => {
                     Some(LoginInfo::ByPassword {
                         user_identify_pointer: UserIdentifyPointer::user_id(user_id),
                         password,
-                        totp: self.totp
+                        totp,
                     })
                 }
                 (None, None) => {
@@ -89,69 +258,205 @@ impl Args {
             None
         };
 
-        let colored = match self.color_policy {
-            ColorPolicy::Always => true,
-            ColorPolicy::Auto => std::io::stdout().is_terminal(),
-            ColorPolicy::Never => false
-        };
+        if matches!(self.sub_command, ToolSubCommand::Login) && login_info.is_none() {
+            bail!("You must provide credential (--password or --read-token-from-stdin) to login.");
+        }
 
-        let platform = match self.platform {
-            None => {
-                warn!("Deprecated (implicitly implying --platform): in the next major version, the --platform flag would be require to set manually.\
+        let platform = self.platform.unwrap_or_else(|| {
+            warn!("Deprecated (implicitly implying --platform): in the next major version, the --platform flag would be require to set manually.\
                 To fix this warning, include `--platform Neos` your command line.");
-                Platform::Neos
-            }
-            Some(Platform::Neos) => Platform::Neos,
-            Some(Platform::Resonite) => bail!("Resonite is not supported yet, please see https://github.com/KisaragiEffective/reinventory-manager/issues/386 for progress")
-        };
+            Platform::Neos
+        });
+
+        let config = Config::load(self.config.as_deref())?;
+        let api_base = self.api_base
+            .or(config.api_base)
+            .map_or_else(|| platform.default_api_base().to_string(), |url| url.as_str().trim_end_matches('/').to_string());
+        debug!("API endpoint: {api_base}");
+
+        let ca_certificate = self.ca_cert.or(config.ca_cert);
+        if cfg!(not(feature = "https_os_native")) && ca_certificate.is_some() {
+            bail!("--ca-cert and `ca-cert` in the configuration file are not supported by this build. Please build with https_os_native.");
+        }
 
         Ok(AfterArgs {
             login_info,
+            totp_secret_file: self.totp_secret_file,
             sub_command: self.sub_command,
-            log_level: self.log_level,
             read_token_from_stdin: self.read_token_from_stdin,
+            remember_me: self.remember_me,
             keep_record_id: self.keep_record_id,
-            colored,
+            jobs: self.jobs,
+            colored: self.color_policy.should_color(),
             platform,
+            api_base,
+            http: HttpClientConfig {
+                connect_timeout: Duration::from_secs(self.connect_timeout.or(config.connect_timeout).unwrap_or(30)),
+                read_timeout: Duration::from_secs(self.read_timeout.or(config.read_timeout).unwrap_or(60)),
+                proxy: self.proxy.or(config.proxy),
+                ca_certificate,
+                retry_policy: RetryPolicy {
+                    max_retries: self.max_retries,
+                    base_delay: Duration::from_millis(self.retry_base_delay),
+                    max_delay: Duration::from_millis(self.retry_max_delay),
+                },
+                cassette: self.record_http.map(CassetteMode::Record)
+                    .or_else(|| self.replay_http.map(CassetteMode::Replay)),
+            },
         })
     }
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct AfterArgs {
     pub login_info: Option<LoginInfo>,
+    /// The code is generated from this only when it logs in with password.
+    pub totp_secret_file: Option<PathBuf>,
     pub sub_command: ToolSubCommand,
-    pub log_level: LogLevel,
     pub read_token_from_stdin: bool,
+    pub remember_me: bool,
     pub keep_record_id: bool,
+    pub jobs: usize,
     pub colored: bool,
     pub platform: Platform,
+    /// Never ends with `/`.
+    pub api_base: String,
+    pub http: HttpClientConfig,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum ToolSubCommand {
     List {
-        #[clap(short = 'd', long, default_value_t = 1)]
+        /// Descends into child directories up to this depth. `1` lists only `base_dir` itself.
+        #[clap(short = 'd', long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
         max_depth: usize,
-        #[clap(short = 'u', long)]
-        target_user: Option<UserId>,
+        /// Descends into child directories without depth limit.
+        #[clap(short = 'R', long, conflicts_with = "max_depth")]
+        recursive: bool,
+        /// jsonl, json, csv, tsv, yaml or table.
+        #[clap(short = 'f', long, default_value_t = OutputFormat::default())]
+        format: OutputFormat,
+        /// Comma-separated fields to output, in this order. e.g. `name,id,path,record_type,updated_at`
+        #[clap(long)]
+        fields: Option<FieldSelection>,
+        #[clap(flatten)]
+        filter: Box<RecordFilter>,
+        /// Descends into the directories which are pointed by links, including the ones of other users.
+        #[clap(long)]
+        follow_links: bool,
+        #[clap(flatten)]
+        sort: SortOptions,
+        #[clap(flatten)]
+        owner: OwnerArgs,
+        #[clap(default_value_t = Default::default())]
+        base_dir: AbsoluteInventoryPath,
+    },
+    /// Shows the records under `base_dir` as a tree.
+    Tree {
+        /// Descends into child directories up to this depth. Unlimited if omitted.
+        #[clap(short = 'd', long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        max_depth: Option<usize>,
+        /// Descends into the directories which are pointed by links, including the ones of other users.
+        #[clap(long)]
+        follow_links: bool,
+        #[clap(flatten)]
+        sort: SortOptions,
+        #[clap(flatten)]
+        owner: OwnerArgs,
+        #[clap(default_value_t = Default::default())]
+        base_dir: AbsoluteInventoryPath,
+    },
+    /// Searches records under `base_dir` recursively.
+    Find {
+        /// Descends into child directories up to this depth. Unlimited if omitted.
+        #[clap(short = 'd', long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        max_depth: Option<usize>,
+        /// jsonl, json, csv, tsv, yaml or table.
+        #[clap(short = 'f', long, default_value_t = OutputFormat::default())]
+        format: OutputFormat,
+        /// Comma-separated fields to output, in this order. e.g. `name,id,path,record_type,updated_at`
+        #[clap(long)]
+        fields: Option<FieldSelection>,
+        #[clap(flatten)]
+        filter: Box<RecordFilter>,
+        /// Descends into the directories which are pointed by links, including the ones of other users.
+        #[clap(long)]
+        follow_links: bool,
+        #[clap(flatten)]
+        sort: SortOptions,
+        #[clap(flatten)]
+        owner: OwnerArgs,
+        #[clap(default_value_t = Default::default())]
+        base_dir: AbsoluteInventoryPath,
+    },
+    /// Summarizes the records under `base_dir` recursively: counts per type for each directory,
+    /// the largest directories, the oldest records, and the share of public and listed records.
+    #[clap(alias = "du")]
+    Stats {
+        /// table or json.
+        #[clap(short = 'f', long, default_value_t = ReportFormat::default())]
+        format: ReportFormat,
+        /// Number of entries in the largest directories and the oldest records.
+        #[clap(long, default_value_t = 10)]
+        top: usize,
+        #[clap(flatten)]
+        filter: Box<RecordFilter>,
+        #[clap(flatten)]
+        owner: OwnerArgs,
         #[clap(default_value_t = Default::default())]
         base_dir: AbsoluteInventoryPath,
     },
     Metadata {
-        #[clap(short = 'u', long)]
-        target_user: Option<UserId>,
+        /// jsonl, json, csv, tsv, yaml or table.
+        #[clap(short = 'f', long, default_value_t = OutputFormat::default())]
+        format: OutputFormat,
+        #[clap(flatten)]
+        owner: OwnerArgs,
         #[clap(default_value_t = Default::default())]
         base_dir: AbsoluteInventoryPath,
     },
     Move {
-        #[clap(short = 'u', long)]
-        target_user: UserId,
+        #[clap(flatten)]
+        owner: OwnerArgs,
         #[clap(short, long)]
         record_id: Vec<RecordId>,
         #[clap(long)]
         to: Vec<String>,
     },
+    /// Logs in with given credential, and saves the session for later invocations.
+    Login,
+    /// Revokes the saved session, and deletes it.
+    Logout,
+    /// Serves records API on local, backed by the records in fixture file.
+    MockServer {
+        /// JSON array of records. Each record must have `ownerId`.
+        #[clap(long)]
+        fixture: PathBuf,
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+}
+
+/// Whose inventory is operated.
+#[derive(clap::Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+pub struct OwnerArgs {
+    #[clap(short = 'u', long)]
+    target_user: Option<UserId>,
+    /// Operates the inventory of the group instead of an user.
+    #[clap(short = 'g', long)]
+    target_group: Option<GroupId>,
+}
+
+impl OwnerArgs {
+    pub fn into_owner(self) -> RecordOwner {
+        match (self.target_user, self.target_group) {
+            (Some(user_id), _) => RecordOwner::User(user_id),
+            (None, Some(group_id)) => RecordOwner::Group(group_id),
+            (None, None) => unreachable!("clap must ensure either of them is given"),
+        }
+    }
 }
 
 pub fn init_fern(log_level: LogLevel) -> Result<(), fern::InitError> {
@@ -199,3 +504,18 @@ impl From<LogLevel> for LevelFilter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_totp_secret, OneTimePassword};
+
+    #[test]
+    fn generates_rfc_6238_code() {
+        // the SHA-1 secret of RFC 6238 Appendix B, "12345678901234567890", written as authenticator apps show
+        let secret = decode_totp_secret("gezd gnbv gy3t qojq gezd gnbv gy3t qojq\n").unwrap();
+        assert_eq!(secret, b"12345678901234567890");
+
+        // RFC 6238 gives 94287082 in 8 digits at this time
+        assert_eq!(OneTimePassword::generate(secret, 59), OneTimePassword("287082".to_string()));
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+/// Contents of the configuration file. Every key is optional, and command line takes precedence.
+///
+/// ```toml
+/// api-base = "https://api.example.com"
+/// connect-timeout = 30
+/// read-timeout = 60
+/// proxy = "http://proxy.example.com:8080"
+/// ca-cert = "/etc/ssl/private-ca.pem"
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub api_base: Option<Url>,
+    /// in seconds
+    pub connect_timeout: Option<u64>,
+    /// in seconds
+    pub read_timeout: Option<u64>,
+    pub proxy: Option<Url>,
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl Config {
+    /// Loads from `path`. If `path` is not given, the file on [`Self::default_path`] is used if exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            }
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config file {path}", path = path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("failed to parse config file {path}", path = path.display()))
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        config_directory().map(|dir| dir.join("config.toml"))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/reinventory-manager` on Linux, and its equivalent on other platforms.
+pub fn config_directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("reinventory-manager"))
+}
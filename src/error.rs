@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter};
+use reqwest::StatusCode;
+use crate::model::RecordId;
+
+/// Failure of an operation against inventory.
+#[derive(Debug)]
+pub enum InventoryError {
+    /// Could not talk with the server: connection refused, timed out, interrupted and so on.
+    Network(reqwest::Error),
+    /// The server refused the request because of the session.
+    Auth {
+        resource: String,
+        status: StatusCode,
+    },
+    /// The requested record or directory does not exist, or is not visible from the session.
+    NotFound {
+        resource: String,
+    },
+    /// The server returned status which is not handled by other variants.
+    HttpStatus {
+        resource: String,
+        status: StatusCode,
+    },
+    /// The response does not match the expected schema.
+    Deserialize(serde_json::Error),
+    /// The request was not recorded in the cassette that is being replayed.
+    Replay {
+        resource: String,
+    },
+    /// Could not read or write the saved session.
+    Session(std::io::Error),
+    /// The server refused to create a session.
+    Login(LoginFailure),
+    /// Could not write the result.
+    Output(std::io::Error),
+    /// The record is a directory, which can not be moved yet.
+    DirectoryMove {
+        record_id: RecordId,
+    },
+}
+
+/// Reason of [`InventoryError::Login`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LoginFailure {
+    /// The account does not exist, or the password is wrong.
+    BadCredentials,
+    /// Two-factor authentication code is missing or wrong.
+    TotpRequired,
+    /// Too many failures, or the account is suspended.
+    Locked,
+}
+
+impl InventoryError {
+    /// Exit code of the process that is terminated by this error.
+    ///
+    /// | code | class |
+    /// |------|-------|
+    /// | 3    | [`Self::NotFound`] |
+    /// | 4    | [`Self::Auth`], [`Self::Login`] |
+    /// | 5    | [`Self::HttpStatus`] |
+    /// | 6    | [`Self::Network`] |
+    /// | 7    | [`Self::Deserialize`] |
+    ///
+    /// 1 and 2 are reserved for other failures and invalid command line, respectively.
+    /// [`Self::Replay`], [`Self::Session`], [`Self::Output`] and [`Self::DirectoryMove`] fall into the former.
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Replay { .. } | Self::Session(_) | Self::Output(_) | Self::DirectoryMove { .. } => 1,
+            Self::NotFound { .. } => 3,
+            Self::Auth { .. } | Self::Login(_) => 4,
+            Self::HttpStatus { .. } => 5,
+            Self::Network(_) => 6,
+            Self::Deserialize(_) => 7,
+        }
+    }
+}
+
+impl Display for InventoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "network error: {e}"),
+            Self::Auth { resource, status } => write!(f, "not authorized to access {resource} ({status}): You may want to login?"),
+            Self::NotFound { resource } => write!(f, "not found: {resource}"),
+            Self::HttpStatus { resource, status } => if status.is_server_error() {
+                write!(f, "server error on {resource} ({status}): Please try again in later.")
+            } else {
+                write!(f, "unexpected status on {resource} ({status})")
+            },
+            Self::Deserialize(e) => write!(f, "failed to parse response: {e}. This is critical bug. Please open ticket on https://github.com/KisaragiEffective/neosvr-inventory-management/issues."),
+            Self::Replay { resource } => write!(f, "{resource} is not recorded in the cassette"),
+            Self::Session(e) => write!(f, "failed to access the saved session: {e}"),
+            Self::Login(LoginFailure::BadCredentials) => write!(f, "login failed: wrong email, user id or password"),
+            Self::Login(LoginFailure::TotpRequired) => write!(f, "login failed: two-factor authentication code is missing or wrong. Please provide it by --totp or --totp-secret-file."),
+            Self::Login(LoginFailure::Locked) => write!(f, "login failed: the account is locked. Please wait for a while, or check your account on the official website."),
+            Self::Output(e) => write!(f, "failed to write the result: {e}"),
+            Self::DirectoryMove { record_id } => write!(f, "{record_id} is a directory, which cannot be moved at this time. This is implement restriction. \
+                Please see https://github.com/KisaragiEffective/neosvr-inventory-management/issues/36 for more info."),
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            Self::Deserialize(e) => Some(e),
+            Self::Session(e) | Self::Output(e) => Some(e),
+            Self::Auth { .. } | Self::NotFound { .. } | Self::HttpStatus { .. } | Self::Replay { .. } | Self::Login(_) | Self::DirectoryMove { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for InventoryError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Network(value)
+    }
+}
+
+impl From<serde_json::Error> for InventoryError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Deserialize(value)
+    }
+}
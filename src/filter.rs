@@ -0,0 +1,72 @@
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use crate::model::{Record, RecordId, RecordType};
+
+/// Conditions which records must satisfy to be output. All of given conditions must be satisfied.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RecordFilter {
+    /// Outputs only records of this type. Can be specified multiple times to accept any of them.
+    #[clap(long = "type")]
+    record_type: Vec<RecordType>,
+    /// Outputs only records which have this tag. Can be specified multiple times to require all of them.
+    #[clap(long)]
+    tag: Vec<String>,
+    /// Outputs only records whose name matches this glob, e.g. `*Chair*`.
+    #[clap(long, value_parser = parse_glob)]
+    name_glob: Option<GlobMatcher>,
+    /// Outputs only records whose name matches this regular expression.
+    #[clap(long)]
+    name_regex: Option<Regex>,
+    /// Outputs only records whose asset URI contains this, e.g. the hash of the asset.
+    #[clap(long)]
+    asset_uri: Option<String>,
+    /// Outputs only the record of this id. Can be specified multiple times to accept any of them.
+    #[clap(long)]
+    id: Vec<RecordId>,
+    /// Outputs only records modified at or after this time. RFC 3339 or `YYYY-MM-DD` (in UTC).
+    #[clap(long, value_parser = parse_time)]
+    modified_after: Option<DateTime<Utc>>,
+    /// Outputs only records modified before this time. RFC 3339 or `YYYY-MM-DD` (in UTC).
+    #[clap(long, value_parser = parse_time)]
+    modified_before: Option<DateTime<Utc>>,
+    /// Outputs only public records.
+    #[clap(long, conflicts_with = "private")]
+    public: bool,
+    /// Outputs only private records.
+    #[clap(long)]
+    private: bool,
+    /// Outputs deleted records too. They are hidden by default.
+    #[clap(long)]
+    include_deleted: bool,
+}
+
+impl RecordFilter {
+    pub fn matches(&self, record: &Record) -> bool {
+        (self.include_deleted || !record.is_deleted)
+            && (self.record_type.is_empty() || self.record_type.contains(&record.record_type))
+            && self.tag.iter().all(|tag| record.tags.contains(tag))
+            && self.name_glob.as_ref().map_or(true, |glob| glob.is_match(&record.name))
+            && self.name_regex.as_ref().map_or(true, |regex| regex.is_match(&record.name))
+            && self.asset_uri.as_ref().map_or(true, |part| record.asset_uri.as_ref().is_some_and(|uri| uri.as_str().contains(part.as_str())))
+            && (self.id.is_empty() || self.id.contains(&record.id))
+            && self.modified_after.map_or(true, |after| after <= record.updated_at)
+            && self.modified_before.map_or(true, |before| record.updated_at < before)
+            && (!self.public || record.is_public)
+            && (!self.private || !record.is_public)
+    }
+}
+
+fn parse_glob(s: &str) -> Result<GlobMatcher, globset::Error> {
+    Glob::new(s).map(|glob| glob.compile_matcher())
+}
+
+fn parse_time(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc))
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").context("expected RFC 3339 or YYYY-MM-DD")?;
+    Ok(date.and_time(NaiveTime::MIN).and_utc())
+}
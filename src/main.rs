@@ -1,17 +1,34 @@
 #![deny(clippy::all)]
 #![warn(clippy::pedantic, clippy::nursery)]
 
-use std::io::stdin;
+use std::io::{stdin, stdout};
 use std::process::exit;
 use clap::Parser;
-use log::{debug, error, warn};
-use crate::cli::{Args, LogLevel, Platform, ToolSubCommand};
-use crate::model::{AuthorizationInfo, LoginInfo, SessionToken};
-use crate::operation::PreLogin;
+use log::{debug, error, info, warn};
+use crate::backend::{ApiBackend, HttpClient, InventoryBackend};
+use crate::error::InventoryError;
+use crate::cli::{AfterArgs, Args, LogLevel, OneTimePassword, ToolSubCommand};
+use crate::model::{AuthorizationInfo, LoginInfo, LoginOptions, SessionToken};
+use crate::operation::{LoggedIn, PreLogin, WalkOptions};
+use crate::output::write_items;
+use crate::session::StoredSession;
+use crate::stats::InventoryStats;
+use crate::tree::TreeView;
+use zeroize::Zeroize;
 
 mod operation;
 mod model;
 mod cli;
+mod backend;
+mod config;
+mod mock_server;
+mod error;
+mod session;
+mod output;
+mod filter;
+mod sort;
+mod stats;
+mod tree;
 
 #[cfg(not(any(feature = "https_rustls", feature = "https_os_native")))]
 compile_error!("You must enable HTTPS connection, choose either https_rustls or https_os_native");
@@ -19,79 +36,243 @@ compile_error!("You must enable HTTPS connection, choose either https_rustls or
 #[tokio::main]
 async fn main() {
     let args: Args = Args::parse();
-    let args = args.validate().unwrap();
+    // initialized before validation, so that its errors are logged
     if args.log_level != LogLevel::None {
-        cli::init_fern(args.log_level).unwrap();
+        if let Err(e) = cli::init_fern(args.log_level) {
+            eprintln!("failed to initialize logger: {e}");
+            exit(1);
+        }
     }
 
-    if args.platform != Platform::Neos {
-        panic!("it must be validated");
+    debug!("fern initialized");
+
+    let args = match args.validate() {
+        Ok(args) => args,
+        Err(e) => {
+            error!("{e:#}");
+            exit(2);
+        }
+    };
+
+    if let ToolSubCommand::MockServer { fixture, listen } = &args.sub_command {
+        if let Err(e) = mock_server::serve(fixture, *listen).await {
+            error!("{e:#}");
+            exit(1);
+        }
+        return;
     }
 
-    debug!("fern initialized");
+    let http = match HttpClient::new(args.http.clone()) {
+        Ok(http) => http,
+        Err(e) => {
+            error!("{e:#}");
+            exit(1);
+        }
+    };
+    let result = run(ApiBackend::new(args.platform, http, args.api_base.clone()), args).await;
+
+    if let Err(e) = result {
+        error!("{e}");
+        exit(e.exit_code());
+    }
+}
 
+async fn run<B: InventoryBackend>(backend: B, args: AfterArgs) -> Result<(), InventoryError> {
     let read_token_from_stdin = args.read_token_from_stdin;
     let auth_info = args.login_info.clone();
 
-    let client = if read_token_from_stdin {
+    if matches!(args.sub_command, ToolSubCommand::Logout) {
+        return logout(backend, args).await;
+    }
+
+    // explicit credential takes precedence over the saved session
+    let stored_session = if auth_info.is_none() {
+        load_session(&args)
+    } else {
+        None
+    };
+    let reuses_session = stored_session.is_some();
+
+    let client = if let Some(session) = stored_session {
+        debug!("using saved session of {user_id}", user_id = &session.user_id);
+        PreLogin::from_session_data(backend, Some(session.user_id.clone()), Some(session.to_authorization_info()))
+    } else if read_token_from_stdin {
         if let Some(LoginInfo::ByTokenFromStdin { user_id }) = auth_info {
             let mut buf = String::new();
-            let read_size = stdin().read_line(&mut buf).unwrap();
+            let read_size = match stdin().read_line(&mut buf) {
+                Ok(read_size) => read_size,
+                Err(e) => {
+                    buf.zeroize();
+                    error!("failed to read token from stdin: {e}");
+                    exit(1)
+                }
+            };
             if read_size == 0 {
                 error!("Please provide token from stdin!");
                 exit(1)
             }
-            let auth = AuthorizationInfo::new(user_id.clone(), SessionToken::new(buf));
-            PreLogin::from_session_data(Some(user_id), Some(auth))
+            let auth = AuthorizationInfo::new(user_id.clone(), SessionToken::new(buf.trim_end().to_string()));
+            buf.zeroize();
+            PreLogin::from_session_data(backend, Some(user_id), Some(auth))
         } else {
             unreachable!("Arguments validation must be done at this point")
         }
     } else {
         debug!("login...");
-        let pre = PreLogin::login(args.login_info).await;
+        let options = LoginOptions {
+            machine_id: session::machine_id(),
+            remember_me: args.remember_me,
+        };
+        let login_info = match (args.login_info, &args.totp_secret_file) {
+            (Some(login_info @ LoginInfo::ByPassword { .. }), Some(path)) => match OneTimePassword::generate_from_secret_file(path) {
+                Ok(totp) => Some(login_info.with_totp(totp)),
+                Err(e) => {
+                    error!("{e:#}");
+                    exit(2)
+                }
+            },
+            (login_info, _) => login_info,
+        };
+        let pre = PreLogin::login(backend, login_info, &options).await?;
         debug!("done.");
         pre
     };
 
-    let sub_command = args.sub_command;
+    if matches!(args.sub_command, ToolSubCommand::Login) {
+        let authorization_info = client.authorization_info()
+            .cloned()
+            .expect("Arguments validation must be done at this point");
+        info!("logged in as {user_id}", user_id = &authorization_info.owner_id);
+        return StoredSession::new(args.platform, args.api_base, authorization_info)
+            .save()
+            .map_err(InventoryError::Session);
+    }
+
+    let result = perform(&client, args.sub_command, args.keep_record_id, args.colored, args.jobs).await;
+
+    if reuses_session {
+        // the saved session is revoked by `logout` subcommand
+        return result;
+    }
+
+    // the session should be revoked even if the operation failed
+    let logout = client.logout().await;
+    result.and(logout)
+}
+
+/// Returns the saved session if it is issued by the server which is being used.
+fn load_session(args: &AfterArgs) -> Option<StoredSession> {
+    match StoredSession::load() {
+        Ok(Some(session)) if session.is_for(args.platform, &args.api_base) => Some(session),
+        Ok(Some(_)) => {
+            debug!("saved session is for another server, ignoring");
+            None
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("failed to load the saved session, ignoring: {e}");
+            None
+        }
+    }
+}
+
+async fn logout<B: InventoryBackend>(backend: B, args: AfterArgs) -> Result<(), InventoryError> {
+    let Some(session) = StoredSession::load().map_err(InventoryError::Session)? else {
+        warn!("not logged in");
+        return Ok(())
+    };
+
+    if session.is_for(args.platform, &args.api_base) {
+        let client = PreLogin::from_session_data(backend, Some(session.user_id.clone()), Some(session.to_authorization_info()));
+        match client.logout().await {
+            // already expired or revoked
+            Err(InventoryError::Auth { .. }) => warn!("the saved session is already invalid"),
+            other => other?,
+        }
+    } else {
+        warn!("the saved session is for {api_base}, deleting without revoking", api_base = &session.api_base);
+    }
+
+    StoredSession::delete().map_err(InventoryError::Session)
+}
+
+async fn perform<B: InventoryBackend>(client: &LoggedIn<B>, sub_command: ToolSubCommand, keep_record_id: bool, colored: bool, jobs: usize) -> Result<(), InventoryError> {
     match sub_command {
-        ToolSubCommand::List { max_depth: _, base_dir, target_user } => {
+        ToolSubCommand::List { max_depth, recursive, format, fields, filter, follow_links, sort, base_dir, owner } => {
             debug!("Inventory:");
-            let xs = client.get_directory_items(
-                target_user
-                    .clone()
-                    .expect("To perform this action, I must know user, to see inventory contents."),
+            let xs = client.walk(
+                owner.into_owner(),
                 base_dir.clone(),
-            ).await;
+                WalkOptions { max_depth: (!recursive).then_some(max_depth), concurrency: jobs, follow_links },
+            ).await?;
 
             debug!("record count: {len}", len = xs.len());
             if xs.is_empty() {
                 warn!("response is empty! You may want to login?");
             }
-            for x in xs {
-                println!("{}", serde_json::to_string(&x).unwrap());
+
+            // must be sorted before filtering, because it relies on the hierarchy
+            let mut xs = sort.sort(xs);
+            xs.retain(|x| filter.matches(&x.record));
+            debug!("record count after filtering: {len}", len = xs.len());
+            write_items(&mut stdout().lock(), &xs, format, fields.as_ref()).map_err(InventoryError::Output)?;
+        }
+        ToolSubCommand::Tree { max_depth, follow_links, sort, owner, base_dir } => {
+            let xs = client.walk(
+                owner.into_owner(),
+                base_dir.clone(),
+                WalkOptions { max_depth, concurrency: jobs, follow_links },
+            ).await?;
+            let xs = sort.sort(xs);
+
+            print!("{}", TreeView::new(&base_dir, &xs, max_depth, colored));
+        }
+        ToolSubCommand::Find { max_depth, format, fields, filter, follow_links, sort, owner, base_dir } => {
+            let xs = client.walk(
+                owner.into_owner(),
+                base_dir.clone(),
+                WalkOptions { max_depth, concurrency: jobs, follow_links },
+            ).await?;
+            debug!("searched {len} records", len = xs.len());
+
+            let mut xs = sort.sort(xs);
+            xs.retain(|x| filter.matches(&x.record));
+            if xs.is_empty() {
+                info!("no record matched");
             }
+            write_items(&mut stdout().lock(), &xs, format, fields.as_ref()).map_err(InventoryError::Output)?;
+        }
+        ToolSubCommand::Stats { format, top, filter, owner, base_dir } => {
+            let xs = client.walk(
+                owner.into_owner(),
+                base_dir.clone(),
+                // links are not followed, because records in other directories would be counted in the link
+                WalkOptions { max_depth: None, concurrency: jobs, follow_links: false },
+            ).await?;
+
+            InventoryStats::compute(&base_dir, &xs, &filter, top)
+                .write(&mut stdout().lock(), format)
+                .map_err(InventoryError::Output)?;
         }
-        ToolSubCommand::Metadata { target_user, base_dir } => {
+        ToolSubCommand::Metadata { format, owner, base_dir } => {
             debug!("Directory metadata:");
             let res = client.get_directory_metadata(
-                target_user
-                    .clone()
-                    .expect("To perform this action, I must know user, to see inventory contents."),
+                owner.into_owner(),
                 base_dir.clone(),
-            ).await;
-            println!("{}", serde_json::to_string(&res).unwrap());
+            ).await?;
+            write_items(&mut stdout().lock(), &[res], format, None).map_err(InventoryError::Output)?;
         }
-        ToolSubCommand::Move { target_user, record_id: record_id_list, to } => {
-            let owner_id = target_user.clone();
+        ToolSubCommand::Move { owner, record_id: record_id_list, to } => {
             client.move_records(
-                owner_id.clone(),
+                owner.into_owner(),
                 record_id_list.clone(),
                 to.clone(),
-                args.keep_record_id
-            ).await;
+                keep_record_id
+            ).await?;
         }
+        ToolSubCommand::Login | ToolSubCommand::Logout => unreachable!("session management must be handled before"),
+        ToolSubCommand::MockServer { .. } => unreachable!("mock server must be handled before login"),
     }
 
-    client.logout().await;
+    Ok(())
 }
@@ -0,0 +1,260 @@
+//! Stand-in server of the records API, for rehearsal and offline testing.
+//! It speaks Neos flavor, so point the tool with `--platform Neos --api-base http://<listen>`.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use crate::backend::{InMemoryBackend, InventoryBackend};
+use crate::error::InventoryError;
+use crate::model::{AbsoluteInventoryPath, AuthorizationInfo, DirectoryMetadata, LoginInfo, LoginOptions, Password, Record, RecordId, RecordOwner, Secret, UserId, UserIdentifyPointer};
+
+type Backend = Arc<InMemoryBackend>;
+
+/// Serves records in `fixture` until the process is terminated.
+/// `fixture` is JSON array of [`Record`]s, and each record must have `ownerId` which points an user or a group.
+pub async fn serve(fixture: &Path, listen: SocketAddr) -> Result<()> {
+    let backend = Arc::new(InMemoryBackend::from_records(load_fixture(fixture)?));
+
+    let listener = tokio::net::TcpListener::bind(listen).await
+        .with_context(|| format!("failed to listen on {listen}"))?;
+    info!("mock server is listening on {listen}");
+
+    axum::serve(listener, router(backend)).await?;
+    Ok(())
+}
+
+fn router(backend: Backend) -> Router {
+    let mut app = Router::new()
+        .route("/userSessions", post(login).patch(extend_session))
+        .route("/userSessions/{owner_id}/{token}", delete(logout));
+
+    // owner is distinguished by its prefix, so handlers are shared
+    for owners in ["users", "groups"] {
+        app = app
+            .route(&format!("/{owners}/{{owner}}/records"), get(get_directory_items))
+            .route(&format!("/{owners}/{{owner}}/records/root/{{*path}}"), get(get_directory_metadata))
+            .route(&format!("/{owners}/{{owner}}/records/{{record_id}}"), get(get_record).put(put_record).delete(delete_record));
+    }
+
+    app.with_state(backend)
+}
+
+fn load_fixture(fixture: &Path) -> Result<Vec<Record>> {
+    let content = fs::read_to_string(fixture)
+        .with_context(|| format!("failed to read fixture {fixture}", fixture = fixture.display()))?;
+
+    serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse fixture {fixture}", fixture = fixture.display()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginBody {
+    /// `None` if the client logs in by email, which can not be told from the fixture.
+    owner_id: Option<UserId>,
+    password: Password,
+    secret_machine_id: Secret<String>,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+async fn login(State(backend): State<Backend>, Json(body): Json<LoginBody>) -> Result<Response, InventoryError> {
+    let Some(owner_id) = body.owner_id else {
+        warn!("refused login by email, as it is not supported");
+        return Ok((StatusCode::NOT_IMPLEMENTED, "the mock server identifies users only by user id. Please login with --user-id instead of --email.").into_response())
+    };
+
+    let login_info = LoginInfo::ByPassword {
+        user_identify_pointer: UserIdentifyPointer::user_id(owner_id),
+        password: body.password,
+        totp: None,
+    };
+
+    let options = LoginOptions {
+        machine_id: body.secret_machine_id,
+        remember_me: body.remember_me,
+    };
+
+    let AuthorizationInfo { owner_id, token, .. } = backend.login(login_info, &options).await?;
+
+    Ok(Json(json!({
+        "userId": owner_id,
+        "token": token,
+    })).into_response())
+}
+
+async fn logout() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn extend_session() -> StatusCode {
+    StatusCode::OK
+}
+
+#[derive(Deserialize)]
+struct DirectoryQuery {
+    path: String,
+}
+
+async fn get_directory_items(State(backend): State<Backend>, UrlPath(owner): UrlPath<RecordOwner>, Query(query): Query<DirectoryQuery>) -> Result<Json<Vec<Record>>, InventoryError> {
+    let path = AbsoluteInventoryPath::from_record_path(&query.path);
+
+    backend.get_directory_items(None, &owner, &path).await.map(Json)
+}
+
+async fn get_directory_metadata(State(backend): State<Backend>, UrlPath((owner, path)): UrlPath<(RecordOwner, String)>) -> Result<Json<DirectoryMetadata>, InventoryError> {
+    let Ok(path) = AbsoluteInventoryPath::from_str(&path);
+
+    backend.get_directory_metadata(None, &owner, &path).await.map(Json)
+}
+
+async fn get_record(State(backend): State<Backend>, UrlPath((owner, record_id)): UrlPath<(RecordOwner, RecordId)>) -> Result<Json<Record>, InventoryError> {
+    backend.get_record(None, &owner, &record_id).await.map(Json)
+}
+
+async fn put_record(State(backend): State<Backend>, UrlPath((owner, record_id)): UrlPath<(RecordOwner, RecordId)>, Json(record): Json<Record>) -> Result<StatusCode, InventoryError> {
+    if record.id != record_id {
+        return Ok(StatusCode::BAD_REQUEST)
+    }
+
+    backend.put_record(None, &owner, &record).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn delete_record(State(backend): State<Backend>, UrlPath((owner, record_id)): UrlPath<(RecordOwner, RecordId)>) -> Result<StatusCode, InventoryError> {
+    backend.delete_record(None, &owner, &record_id).await?;
+    Ok(StatusCode::OK)
+}
+
+impl IntoResponse for InventoryError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::Auth { status, .. } | Self::HttpStatus { status, .. } => *status,
+            Self::Login(_) => StatusCode::FORBIDDEN,
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::DirectoryMove { .. } => StatusCode::BAD_REQUEST,
+            Self::Network(_) | Self::Deserialize(_) | Self::Replay { .. } | Self::Session(_) | Self::Output(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use email_address::EmailAddress;
+    use reqwest::StatusCode;
+    use crate::backend::{ApiBackend, HttpClient, HttpClientConfig, InMemoryBackend, InventoryBackend, RetryPolicy};
+    use crate::backend::fixture::{owner, path, record};
+    use crate::cli::Platform;
+    use crate::error::InventoryError;
+    use crate::model::{LoginInfo, LoginOptions, Password, RecordId, RecordType, Secret, UserIdentifyPointer};
+    use crate::operation::{LoggedIn, PreLogin};
+    use super::router;
+
+    /// Starts the mock server on an ephemeral port, and returns its address as API base.
+    async fn spawn() -> String {
+        let backend = InMemoryBackend::from_records(vec![
+            record("U-test", "R-work", "Inventory", "Work", RecordType::Directory),
+            record("U-test", "R-chair", "Inventory", "Chair", RecordType::Object),
+            record("G-team", "R-shared", "Inventory", "Shared", RecordType::Object),
+        ]);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(Arc::new(backend))).await });
+
+        format!("http://{listen}")
+    }
+
+    fn neos(api_base: String) -> ApiBackend {
+        let http = HttpClient::new(HttpClientConfig {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(5),
+            proxy: None,
+            ca_certificate: None,
+            retry_policy: RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            },
+            cassette: None,
+        }).unwrap();
+
+        ApiBackend::new(Platform::Neos, http, api_base)
+    }
+
+    async fn login(backend: ApiBackend, user_identify_pointer: UserIdentifyPointer) -> Result<LoggedIn<ApiBackend>, InventoryError> {
+        let login_info = LoginInfo::ByPassword {
+            user_identify_pointer,
+            password: Password::new("password".to_string()),
+            totp: None,
+        };
+        let options = LoginOptions {
+            machine_id: Secret::new("machine".to_string()),
+            remember_me: false,
+        };
+
+        PreLogin::login(backend, Some(login_info), &options).await
+    }
+
+    #[tokio::test]
+    async fn routes_records_of_users_and_groups() {
+        let client = login(neos(spawn().await), UserIdentifyPointer::user_id("U-test".parse().unwrap())).await.unwrap();
+
+        let items = client.get_directory_items(owner("U-test"), path("Inventory")).await.unwrap();
+        assert_eq!(items.len(), 2);
+        let shared = client.get_directory_items(owner("G-team"), path("Inventory")).await.unwrap();
+        assert_eq!(shared[0].id.0, "R-shared");
+
+        let metadata = client.get_directory_metadata(owner("U-test"), path("Inventory/Work")).await.unwrap();
+        assert_eq!(serde_json::to_value(metadata).unwrap()["id"], "R-work");
+
+        client.logout().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn moves_record_over_http() {
+        let client = login(neos(spawn().await), UserIdentifyPointer::user_id("U-test".parse().unwrap())).await.unwrap();
+
+        client.move_records(owner("U-test"), vec![RecordId("R-chair".to_string())], vec!["Inventory".to_string(), "Work".to_string()], false).await.unwrap();
+
+        let moved = client.get_directory_items(owner("U-test"), path("Inventory/Work")).await.unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].name, "Chair");
+    }
+
+    #[tokio::test]
+    async fn maps_missing_records_to_not_found() {
+        let backend = neos(spawn().await);
+
+        let record = backend.get_record(None, &owner("U-test"), &RecordId("R-missing".to_string())).await;
+        assert!(matches!(record, Err(InventoryError::NotFound { .. })));
+        let metadata = backend.get_directory_metadata(None, &owner("U-test"), &path("Inventory/Missing")).await;
+        assert!(matches!(metadata, Err(InventoryError::NotFound { .. })));
+        let deleted = backend.delete_record(None, &owner("U-test"), &RecordId("R-missing".to_string())).await;
+        assert!(matches!(deleted, Err(InventoryError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn login_by_email_is_not_supported() {
+        let email = EmailAddress::new_unchecked("test@example.com");
+        let result = login(neos(spawn().await), UserIdentifyPointer::email(email)).await;
+
+        // must not be taken as wrong credential
+        assert!(matches!(result, Err(InventoryError::HttpStatus { status: StatusCode::NOT_IMPLEMENTED, .. })));
+    }
+}
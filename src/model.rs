@@ -1,19 +1,24 @@
+use std::borrow::Cow;
 use std::convert::Infallible;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use url::Url;
 use derive_more::{Display, FromStr};
 use std::str::FromStr;
 use email_address::EmailAddress;
-use serde::{Serialize, Deserialize, Deserializer};
+use serde::{Serialize, Deserialize, Deserializer, Serializer};
 use serde::de::Error;
 use anyhow::ensure;
 use base64::Engine;
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
-use log::debug;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, TimeZone, Utc};
 use uuid::Uuid;
-use crate::cli::OneTimePassword;
-
-#[derive(Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
+use serde_json::Value;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use strum::EnumString;
+use zeroize::Zeroize;
+use crate::cli::{OneTimePassword, Platform};
+
+#[derive(Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug, Hash)]
 pub struct UserId(String);
 
 impl FromStr for UserId {
@@ -26,11 +31,11 @@ impl FromStr for UserId {
 }
 
 // TODO: "R-" {GUID}という形式に沿ってパースする
-#[derive(FromStr, Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
+#[derive(FromStr, Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug, Hash)]
 /// This is thin pointer to the actual Record. It is unique, and has one-by-one relation with Record.
 pub struct RecordId(pub String);
 
-#[derive(Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
+#[derive(Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug, Hash)]
 pub struct GroupId(String);
 
 impl FromStr for GroupId {
@@ -42,15 +47,79 @@ impl FromStr for GroupId {
     }
 }
 
-#[derive(FromStr, Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
-pub struct Password(String);
+/// Credential that must not be leaked into logs. `Debug` and `Display` print placeholder, and it is
+/// zeroized on drop. The value can be taken only through [`Self::expose_secret`].
+#[derive(Deserialize, Eq, PartialEq, Clone)]
+#[serde(transparent)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub const fn new(inner: T) -> Self {
+        Self(inner)
+    }
+
+    pub const fn expose_secret(&self) -> &T {
+        &self.0
+    }
+
+    /// For `#[serde(serialize_with)]`: request bodies have to carry the actual value.
+    pub fn serialize_exposed<S: Serializer>(secret: &Self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+    {
+        secret.0.serialize(serializer)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
 
-#[derive(FromStr, Display, Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
-pub struct SessionToken(String);
+impl<T: Zeroize> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T: Zeroize> Display for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
+pub struct Password(#[serde(serialize_with = "Secret::serialize_exposed")] Secret<String>);
+
+impl Password {
+    pub const fn new(inner: String) -> Self {
+        Self(Secret::new(inner))
+    }
+
+    pub const fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+impl FromStr for Password {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s.to_string()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug)]
+pub struct SessionToken(#[serde(serialize_with = "Secret::serialize_exposed")] Secret<String>);
 
 impl SessionToken {
     pub const fn new(inner: String) -> Self {
-        Self(inner)
+        Self(Secret::new(inner))
+    }
+
+    pub const fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
     }
 }
 
@@ -69,6 +138,19 @@ pub enum LoginInfo {
 }
 
 impl LoginInfo {
+    /// Replaces two-factor authentication code. This is no-op if the credential is not a password.
+    #[must_use]
+    pub fn with_totp(self, totp: OneTimePassword) -> Self {
+        match self {
+            Self::ByPassword { user_identify_pointer, password, .. } => Self::ByPassword {
+                user_identify_pointer,
+                password,
+                totp: Some(totp),
+            },
+            other @ Self::ByTokenFromStdin { .. } => other,
+        }
+    }
+
     pub const fn get_totp(&self) -> &Option<OneTimePassword> {
         match self {
             Self::ByPassword { totp, .. } => totp,
@@ -103,33 +185,80 @@ impl UserIdentifyPointer {
     }
 }
 
+/// Parameters of login other than credentials.
+#[derive(Debug, Clone)]
+pub struct LoginOptions {
+    /// The service treats sessions with the same id as the ones from the same machine.
+    pub machine_id: Secret<String>,
+    /// Requests long-lived session.
+    pub remember_me: bool,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 /// body: POST /userSessions
 pub struct UserLoginPostBody {
     #[serde(flatten)]
     login_method: LoginInfo,
-    #[serde(rename = "secretMachineId")]
-    generated_machine_id: String,
+    #[serde(rename = "secretMachineId", serialize_with = "Secret::serialize_exposed")]
+    machine_id: Secret<String>,
     remember_me: bool,
 }
 
 /// response: POST /userSessions
 impl UserLoginPostBody {
-    pub fn create(login_method: LoginInfo, remember_me: bool) -> Self {
-        use base64::engine::GeneralPurpose as Base64Engine;
-        let random_uuid = Uuid::new_v4().to_string();
-        let random_uuid = random_uuid.as_bytes();
-        static BASE64_ENGINE: Base64Engine = Base64Engine::new(
-            &base64::alphabet::URL_SAFE,
-            base64::engine::GeneralPurposeConfig::new().with_encode_padding(false)
-        );
-        let nonce = BASE64_ENGINE.encode(random_uuid);
-
+    pub fn create(login_method: LoginInfo, options: &LoginOptions) -> Self {
         Self {
             login_method,
-            generated_machine_id: nonce,
-            remember_me
+            machine_id: options.machine_id.clone(),
+            remember_me: options.remember_me,
+        }
+    }
+}
+
+pub fn generate_machine_id() -> String {
+    use base64::engine::GeneralPurpose as Base64Engine;
+    static BASE64_ENGINE: Base64Engine = Base64Engine::new(
+        &base64::alphabet::URL_SAFE,
+        base64::engine::GeneralPurposeConfig::new().with_encode_padding(false)
+    );
+
+    let random_uuid = Uuid::new_v4().to_string();
+    let random_uuid = random_uuid.as_bytes();
+    BASE64_ENGINE.encode(random_uuid)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// body: POST /userSessions (Resonite)
+pub struct ResoniteUserLoginPostBody {
+    #[serde(flatten)]
+    user_identify_pointer: UserIdentifyPointer,
+    authentication: ResoniteAuthentication,
+    #[serde(rename = "secretMachineId", serialize_with = "Secret::serialize_exposed")]
+    machine_id: Secret<String>,
+    remember_me: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "$type", rename_all = "camelCase")]
+enum ResoniteAuthentication {
+    Password {
+        password: Password,
+    },
+}
+
+impl ResoniteUserLoginPostBody {
+    /// Returns `None` if `login_method` does not carry credentials.
+    pub fn create(login_method: LoginInfo, options: &LoginOptions) -> Option<Self> {
+        match login_method {
+            LoginInfo::ByPassword { user_identify_pointer, password, .. } => Some(Self {
+                user_identify_pointer,
+                authentication: ResoniteAuthentication::Password { password },
+                machine_id: options.machine_id.clone(),
+                remember_me: options.remember_me,
+            }),
+            LoginInfo::ByTokenFromStdin { .. } => None,
         }
     }
 }
@@ -139,6 +268,8 @@ impl UserLoginPostBody {
 pub struct UserLoginPostResponse {
     pub user_id: UserId,
     pub token: SessionToken,
+    #[serde(default)]
+    pub expire: Option<DateTime<Utc>>,
 }
 
 impl UserLoginPostResponse {
@@ -146,27 +277,45 @@ impl UserLoginPostResponse {
         AuthorizationInfo {
             owner_id: self.user_id.clone(),
             token: self.token.clone(),
+            expire: self.expire,
+            lifetime: self.expire.map(|expire| expire - Utc::now()),
         }
     }
 }
 
+#[derive(Deserialize)]
+/// response: POST /userSessions (Resonite)
+pub struct ResoniteUserLoginPostResponse {
+    /// Resonite wraps the session into `entity`, and its shape is same as Neos.
+    pub entity: UserLoginPostResponse,
+}
+
 #[derive(Debug, Clone)]
 pub struct AuthorizationInfo {
     pub owner_id: UserId,
     pub token: SessionToken,
+    /// `None` if unknown, for example the token is given from stdin.
+    pub expire: Option<DateTime<Utc>>,
+    /// How long the session lasts after it is created or extended. `None` if unknown.
+    pub lifetime: Option<TimeDelta>,
 }
 
 impl AuthorizationInfo {
-    pub fn as_authorization_header_value(&self) -> String {
-        let val = format!("neos {owner_id}:{auth_token}", owner_id = self.owner_id.0, auth_token = self.token.0);
-        debug!("auth: {val}");
-        val
+    pub fn as_authorization_header_value(&self, platform: Platform) -> String {
+        format!(
+            "{scheme} {owner_id}:{auth_token}",
+            scheme = platform.authorization_scheme(),
+            owner_id = self.owner_id.0,
+            auth_token = self.token.expose_secret(),
+        )
     }
 
     pub const fn new(owner_id: UserId, token: SessionToken) -> Self {
         Self {
             owner_id,
             token,
+            expire: None,
+            lifetime: None,
         }
     }
 }
@@ -183,14 +332,50 @@ struct RecordGetBody {
     record_id: RecordId,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Display, Serialize, Debug, Eq, PartialEq, Clone, Hash)]
 #[serde(untagged)]
 pub enum RecordOwner {
     User(UserId),
     Group(GroupId),
 }
 
-#[derive(Display, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+impl RecordOwner {
+    /// Path segments which the records API of this owner is placed under, such as `users/U-xxx`.
+    pub fn api_path(&self) -> String {
+        match self {
+            Self::User(user_id) => format!("users/{user_id}"),
+            Self::Group(group_id) => format!("groups/{group_id}"),
+        }
+    }
+}
+
+impl FromStr for RecordOwner {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("G-") {
+            GroupId::from_str(s).map(Self::Group)
+        } else {
+            UserId::from_str(s).map(Self::User)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordOwner {
+    /// Distinguished by the prefix, because both of them are plain string.
+    /// Unlike [`FromStr`], this does not reject unknown prefix in order to accept whatever the server returns.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        if raw.starts_with("G-") {
+            Ok(Self::Group(GroupId(raw)))
+        } else {
+            Ok(Self::User(UserId(raw)))
+        }
+    }
+}
+
+#[derive(Display, EnumString, Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[strum(ascii_case_insensitive)]
 pub enum RecordType {
     Directory,
     Object,
@@ -254,9 +439,9 @@ pub struct Record {
     pub thumbnail_uri: Option<Url>,
     #[serde(rename = "creationTime", default)]
     // Essential Toolsだと欠けている
-    created_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
     #[serde(rename = "lastModificationTime", deserialize_with = "fallback_to_utc")]
-    updated_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub random_order: i32,
     pub visits: i32,
     pub rating: f64,
@@ -267,6 +452,65 @@ pub struct Record {
     pub submissions: Vec<Submission>
 }
 
+/// Destination of [`RecordType::Link`], which is written in [`Record::asset_uri`]
+/// as `neosrec:///U-example/R-xxx` (or `resrec:///...` on Resonite).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecordLink {
+    pub owner: RecordOwner,
+    pub record_id: RecordId,
+}
+
+impl RecordLink {
+    /// Returns `None` if `uri` does not point a record.
+    pub fn from_uri(uri: &Url) -> Option<Self> {
+        if !matches!(uri.scheme(), "neosrec" | "resrec") {
+            return None
+        }
+
+        let mut segments = uri.path_segments()?;
+        let owner = segments.next()?.parse().ok()?;
+        let record_id = segments.next().filter(|id| id.starts_with("R-"))?;
+        if segments.next().is_some() {
+            return None
+        }
+
+        Some(Self { owner, record_id: RecordId(record_id.to_string()) })
+    }
+}
+
+/// Rich-text tags such as `<color=red>` and `</b>`.
+static RICH_TEXT_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"</?[A-Za-z][^<>]*>").expect("must be valid regex"));
+
+impl Record {
+    /// The name without rich-text tags, i.e. how it looks like in game.
+    pub fn plain_name(&self) -> Cow<'_, str> {
+        RICH_TEXT_TAG.replace_all(&self.name, "")
+    }
+}
+
+/// Resonite nests `globalVersion`, `localVersion`, `lastModifyingUserId` and `lastModifyingMachineId`
+/// into `version` object. This moves them to top-level so that the result has same shape as Neos.
+pub fn flatten_resonite_version(value: &mut Value) {
+    if let Some(object) = value.as_object_mut() {
+        if let Some(Value::Object(version)) = object.remove("version") {
+            object.extend(version);
+        }
+    }
+}
+
+/// Inverse of [`flatten_resonite_version`].
+pub fn nest_resonite_version(value: &mut Value) {
+    const VERSION_KEYS: [&str; 4] = ["globalVersion", "localVersion", "lastModifyingUserId", "lastModifyingMachineId"];
+
+    if let Some(object) = value.as_object_mut() {
+        let version = VERSION_KEYS
+            .iter()
+            .filter_map(|key| object.remove(*key).map(|v| ((*key).to_string(), v)))
+            .collect();
+        object.insert("version".to_string(), Value::Object(version));
+    }
+}
+
 /// Essential Toolsだとタイムゾーンが欠けているのでパースに失敗する (?!)
 /// see: <https://github.com/Neos-Metaverse/NeosPublic/issues/3714>
 fn fallback_to_utc<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
@@ -333,9 +577,35 @@ pub struct DirectoryMetadata {
 
 }
 
+impl DirectoryMetadata {
+    /// Returns `None` if `record` is not a directory, or lacks some fields.
+    pub fn from_directory_record(record: &Record) -> Option<Self> {
+        if record.record_type != RecordType::Directory {
+            return None
+        }
+
+        Some(Self {
+            id: record.id.clone(),
+            global_version: record.global_version.try_into().ok()?,
+            local_version: record.local_version.try_into().ok()?,
+            last_modify_user: record.last_update_by.clone()?,
+            last_modify_machine_user: record.last_update_machine.clone()?,
+            name: record.name.clone(),
+            owner_name: record.owner_name.clone()?,
+            path: record.path.clone(),
+            is_public: record.is_public,
+            is_for_patrons: record.is_for_patrons,
+            is_listed: record.is_listed,
+            is_deleted: record.is_deleted,
+            created_at: record.created_at?,
+            updated_at: record.updated_at,
+        })
+    }
+}
+
 /// インベントリのルートを起点とする絶対パスを表現する。
 /// 要素に`.`や`..`が入っていても、特別な意味を持たず、文字通り扱われることに注意。
-#[derive(Eq, PartialEq, Default, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Default, Debug, Clone)]
 pub struct AbsoluteInventoryPath {
     inner: Vec<String>
 }
@@ -348,6 +618,31 @@ impl AbsoluteInventoryPath {
     pub fn to_absolute_path(&self) -> String {
         self.inner.join("/")
     }
+
+    /// Returns the representation which is used in [`Record::path`].
+    pub fn to_record_path(&self) -> String {
+        self.inner.join("\\")
+    }
+
+    /// Inverse of [`Self::to_record_path`].
+    pub fn from_record_path(path: &str) -> Self {
+        Self { inner: path.split('\\').map(std::string::ToString::to_string).collect() }
+    }
+
+    /// Returns the path of the child named `name`.
+    pub fn join(&self, name: &str) -> Self {
+        let mut inner = self.inner.clone();
+        inner.push(name.to_string());
+
+        Self { inner }
+    }
+
+    /// Splits into the parent directory and the last component.
+    pub fn split_last(&self) -> Option<(Self, &str)> {
+        let (last, parent) = self.inner.split_last()?;
+
+        Some((Self { inner: parent.to_vec() }, last.as_str()))
+    }
 }
 
 impl FromStr for AbsoluteInventoryPath {
@@ -1,273 +1,230 @@
-use std::sync::Arc;
-use reqwest::header::AUTHORIZATION;
-use log::{debug, error, info, warn};
-use once_cell::sync::Lazy;
-use reqwest::{Client, ClientBuilder};
+use std::future::Future;
+use std::io::{stdin, IsTerminal};
+use std::sync::Mutex;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, error, info};
+use reqwest::StatusCode;
 use uuid::Uuid;
+use crate::backend::InventoryBackend;
+use crate::cli::OneTimePassword;
+use crate::error::{InventoryError, LoginFailure};
 use crate::LoginInfo;
-use crate::model::{AuthorizationInfo, DirectoryMetadata, AbsoluteInventoryPath, Record, RecordId, RecordType, UserId, UserLoginPostBody, UserLoginPostResponse};
+use crate::model::{AuthorizationInfo, DirectoryMetadata, AbsoluteInventoryPath, LoginOptions, Record, RecordId, RecordOwner, RecordType, UserId};
 
-static BASE_POINT: &str = "https://api.neos.com/api";
-static CLIENT: Lazy<Arc<Client>> = Lazy::new(|| {
-    let c = ClientBuilder::new().user_agent("NeosVR-Inventory-Manager/0.1");
+mod walk;
 
-    #[cfg(feature = "https_os_native")]
-    let c = c.use_native_tls();
-
-    Arc::new(
-        c
-            .build()
-            .expect("failed to initialize HTTP client")
-    )
-});
+pub use walk::{WalkOptions, WalkedRecord};
 
 pub struct PreLogin;
 
 impl PreLogin {
-    pub async fn login(login_info: Option<LoginInfo>) -> LoggedIn {
-        if let Some(auth) = login_info {
-            let mut req = CLIENT
-                .post(format!("{BASE_POINT}/userSessions"));
-
-            if let Some(x) = auth.get_totp() {
-                req = req.header("TOTP", x.0.clone());
-            }
-
-            let token_res = req
-                .json(&UserLoginPostBody::create(auth, false))
-                .send();
+    pub async fn login<B: InventoryBackend>(backend: B, login_info: Option<LoginInfo>, options: &LoginOptions) -> Result<LoggedIn<B>, InventoryError> {
+        let logged_in = if let Some(auth) = login_info {
+            let using_token = match backend.login(auth.clone(), options).await {
+                Err(InventoryError::Login(LoginFailure::TotpRequired)) if stdin().is_terminal() => {
+                    error!("{e}", e = InventoryError::Login(LoginFailure::TotpRequired));
+                    let Ok(totp) = OneTimePassword::prompt() else {
+                        return Err(InventoryError::Login(LoginFailure::TotpRequired))
+                    };
 
-            debug!("post 2");
-            let token_res = token_res
-                .await
-                .unwrap()
-                .json::<UserLoginPostResponse>()
-                .await
-                .unwrap();
+                    backend.login(auth.with_totp(totp), options).await?
+                }
+                other => other?,
+            };
 
             debug!("post 3");
-            let using_token = token_res.to_authorization_info();
-            let user_id = token_res.user_id;
+            let user_id = using_token.owner_id.clone();
 
             debug!("post 4");
-            Self::from_session_data(Some(user_id), Some(using_token))
+            Self::from_session_data(backend, Some(user_id), Some(using_token))
         } else {
-            Self::from_session_data(None, None)
-        }
+            Self::from_session_data(backend, None, None)
+        };
+
+        Ok(logged_in)
     }
 
-    pub const fn from_session_data(current_user: Option<UserId>, authorization_info: Option<AuthorizationInfo>) -> LoggedIn {
+    pub fn from_session_data<B: InventoryBackend>(backend: B, current_user: Option<UserId>, authorization_info: Option<AuthorizationInfo>) -> LoggedIn<B> {
+        let session_expire = authorization_info.as_ref().and_then(|a| a.expire);
+        let session_lifetime = authorization_info.as_ref().and_then(|a| a.lifetime);
+
         LoggedIn {
+            backend,
             authorization_info,
             current_user,
+            session_expire: Mutex::new(session_expire),
+            session_lifetime,
         }
     }
 }
 
-pub struct LoggedIn {
+pub struct LoggedIn<B> {
+    backend: B,
     authorization_info: Option<AuthorizationInfo>,
     current_user: Option<UserId>,
+    /// `None` if unknown. The server does not tell new one on extension, so it is estimated from
+    /// `session_lifetime` at that time.
+    session_expire: Mutex<Option<DateTime<Utc>>>,
+    session_lifetime: Option<TimeDelta>,
 }
 
-impl LoggedIn {
-    pub async fn logout(self) {
-        if let Some(authorization_info) = self.authorization_info {
-            let owner_id = authorization_info.owner_id.clone();
-            CLIENT
-                .delete(format!("{BASE_POINT}/userSessions/{owner_id}/{auth_token}", auth_token = authorization_info.token))
-                .header(AUTHORIZATION, authorization_info.as_authorization_header_value())
-                .send()
-                .await
-                .unwrap();
-        }
-    }
+/// The session is extended in advance if it expires within this period.
+const SESSION_EXTENSION_MARGIN: TimeDelta = TimeDelta::minutes(5);
 
-    pub async fn get_directory_items(&self, owner_id: UserId, path: AbsoluteInventoryPath) -> Vec<Record> {
-        let authorization_info = &self.authorization_info;
-        let path = path.to_uri_query_value();
-        // NOTE:
-        // https://api.neos.com/api/users/U-kisaragi-marine/records/root/Inventory/Test <-- これはディレクトリのメタデータを単体で返す
+impl<B: InventoryBackend> LoggedIn<B> {
+    pub const fn authorization_info(&self) -> Option<&AuthorizationInfo> {
+        self.authorization_info.as_ref()
+    }
 
+    pub async fn logout(self) -> Result<(), InventoryError> {
+        if let Some(authorization_info) = &self.authorization_info {
+            self.backend.logout(authorization_info).await?;
+        }
 
-        let endpoint = format!("{BASE_POINT}/users/{owner_id}/records?path={path}");
+        Ok(())
+    }
 
-        debug!("endpoint: {endpoint}", endpoint = &endpoint);
-        {
-            let mut res = CLIENT.get(&endpoint);
+    /// Runs `operation` with the session. The session is extended if it is expiring, or `operation` is
+    /// rejected as unauthorized. In the latter case, `operation` is retried once.
+    async fn with_session<'a, T, F, Fut>(&'a self, operation: F) -> Result<T, InventoryError>
+    where
+        F: Fn(Option<&'a AuthorizationInfo>) -> Fut + Send + Sync,
+        Fut: Future<Output = Result<T, InventoryError>> + Send,
+    {
+        let Some(authorization_info) = &self.authorization_info else {
+            return operation(None).await
+        };
+
+        if self.is_session_expiring() {
+            info!("session is expiring, extending");
+            self.extend_session(authorization_info).await?;
+        }
 
-            if let Some(authorization_info) = authorization_info {
-                res = res.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
+        match operation(Some(authorization_info)).await {
+            Err(InventoryError::Auth { status: StatusCode::UNAUTHORIZED, .. }) => {
+                info!("session seems to be expired, extending and retrying");
+                self.extend_session(authorization_info).await?;
+                operation(Some(authorization_info)).await
             }
-
-            let res = res
-                .send()
-                .await
-                .unwrap()
-                .text()
-                .await
-                .unwrap();
-
-            debug!("raw output: {res}");
+            other => other,
         }
-        let mut res = CLIENT.get(endpoint);
+    }
 
-        if let Some(authorization_info) = authorization_info {
-            res = res.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
-        }
+    fn is_session_expiring(&self) -> bool {
+        self.session_expire
+            .lock()
+            .expect("poisoned")
+            .is_some_and(|expire| expire - Utc::now() < SESSION_EXTENSION_MARGIN)
+    }
+
+    async fn extend_session(&self, authorization_info: &AuthorizationInfo) -> Result<(), InventoryError> {
+        self.backend.extend_session(authorization_info).await?;
+        *self.session_expire.lock().expect("poisoned") = self.session_lifetime.map(|lifetime| Utc::now() + lifetime);
+        debug!("session extended");
+        Ok(())
+    }
 
-        res
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap()
+    pub async fn get_directory_items(&self, owner: RecordOwner, path: AbsoluteInventoryPath) -> Result<Vec<Record>, InventoryError> {
+        self.with_session(|auth| self.backend.get_directory_items(auth, &owner, &path)).await
     }
 
-    pub async fn get_directory_metadata(&self, owner_id: UserId, path: AbsoluteInventoryPath) -> DirectoryMetadata {
+    pub async fn get_directory_metadata(&self, owner: RecordOwner, path: AbsoluteInventoryPath) -> Result<DirectoryMetadata, InventoryError> {
         // NOTE:
         // https://api.neos.com/api/users/U-kisaragi-marine/records/root/Inventory/Test <-- これはディレクトリのメタデータを単体で返す
-        let authorization_info = &self.authorization_info;
-        let path = path.to_absolute_path();
-        let endpoint = format!("{BASE_POINT}/users/{owner_id}/records/root/{path}");
-
-        debug!("endpoint: {endpoint}", endpoint = &endpoint);
-        let mut res = CLIENT.get(endpoint);
-
-        if let Some(authorization_info) = authorization_info {
-            res = res.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
-        }
-
-        res
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap()
+        self.with_session(|auth| self.backend.get_directory_metadata(auth, &owner, &path)).await
     }
 
-    pub async fn move_records(&self, owner_id: UserId, records_to_move: Vec<RecordId>, to: Vec<String>, keep_record_id: bool) {
-        let authorization_info = &self.authorization_info;
-
+    pub async fn move_records(&self, owner: RecordOwner, records_to_move: Vec<RecordId>, to: Vec<String>, keep_record_id: bool) -> Result<(), InventoryError> {
         for record_id in records_to_move {
             debug!("checking {record_id}", record_id = &record_id);
-            let find = self.get_record(owner_id.clone(), record_id.clone()).await;
-
-            if let Some(found_record) = find {
-                if found_record.record_type == RecordType::Directory {
-                    // TODO: fix this
-                    error!("Directories cannot be moved at this time. This is implement restriction. \
-                Please see https://github.com/KisaragiEffective/neosvr-inventory-management/issues/36 for more info.");
-                    return;
-                }
+            let found_record = self.get_record(owner.clone(), record_id.clone()).await?;
 
-                debug!("found, moving");
+            if found_record.record_type == RecordType::Directory {
+                // TODO: fix this
+                return Err(InventoryError::DirectoryMove { record_id });
+            }
 
-                let from = found_record.path.clone();
+            debug!("found, moving");
 
-                // region delete old record
-                {
-                    let endpoint = format!("{BASE_POINT}/users/{owner_id}/records/{record_id}", owner_id = &owner_id);
-                    let mut req = CLIENT.delete(endpoint);
+            let from = found_record.path.clone();
+            let mut record = found_record.clone();
+            record.path = to.join("\\");
 
-                    if let Some(authorization_info) = authorization_info {
-                        req = req.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
-                    }
+            if keep_record_id {
+                // overwriting the record moves it, so nothing is lost even if this fails
+                debug!("record id unchanged");
+                self.with_session(|auth| self.backend.put_record(auth, &owner, &record)).await?;
+                info!("Success! {record_id} for {owner} was moved from {from} to {to}.", to = &record.path);
+                continue;
+            }
 
-                    let deleted = req
-                        .send()
-                        .await
-                        .unwrap();
+            // in case it is deleted but not inserted, the record must be restored from here
+            let dump = serde_json::to_string(&found_record).expect("Record must be serializable");
+            info!("moving {record_id}: {dump}");
 
-                    debug!("deleted: {deleted:?}");
+            // region delete old record
+            if let Err(e) = self.with_session(|auth| self.backend.delete_record(auth, &owner, &record_id)).await {
+                error!("{record_id} may or may not be deleted from {from}: {e}");
+                error!("The record was: {dump}");
+                return Err(e);
+            }
+            // endregion
+            // region insert
+            {
+                debug!("insert!");
+                // GUIDは小文字が「推奨」されているため念の為小文字にしておく
+                let record_id = RecordId(format!("R-{}", Uuid::new_v4().to_string().to_lowercase()));
+                debug!("new record id: {record_id}", record_id = &record_id);
+                record.id = record_id.clone();
+
+                debug!("requesting...");
+                if let Err(e) = self.with_session(|auth| self.backend.put_record(auth, &owner, &record)).await {
+                    // TODO: rollback
+                    error!("{record_id} was deleted from {from}, but could not be inserted: {e}");
+                    error!("The record was: {dump}");
+                    return Err(e);
                 }
-                // endregion
-                // region insert
-                {
-                    debug!("insert!");
-                    let record_id = if keep_record_id {
-                        debug!("record id unchanged");
-                        record_id
-                    } else {
-                        // GUIDは小文字が「推奨」されているため念の為小文字にしておく
-                        let record_id = RecordId(format!("R-{}", Uuid::new_v4().to_string().to_lowercase()));
-                        debug!("new record id: {record_id}", record_id = &record_id);
-                        record_id
-                    };
 
-                    let endpoint = format!("{BASE_POINT}/users/{owner_id}/records/{record_id}", owner_id = &owner_id, record_id = &record_id);
-                    debug!("endpoint: {endpoint}", endpoint = &endpoint);
-                    let mut request = CLIENT.put(endpoint);
-
-                    if let Some(authorization_info) = authorization_info {
-                        debug!("auth set");
-                        request = request.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
-                    }
-
-                    let mut record = found_record.clone();
-                    record.path = to.join("\\");
-                    record.id = record_id.clone();
-
-                    debug!("requesting...");
-                    let res = request
-                        .json(&record)
-                        .send()
-                        .await
-                        .unwrap();
-                    if res.status().is_success() {
-                        info!("Success! {record_id} for {owner_id} was moved from {from} to {to}.", to = to.join("\\"), record_id = &record_id);
-                    } else if res.status().is_client_error() {
-                        error!("Client error ({status}): this is fatal bug. Please report this to bug tracker.", status = res.status());
-                        // TODO: rollback
-                    } else if res.status().is_server_error() {
-                        error!("Server error ({status}): Please try again in later.", status = res.status());
-                    } else {
-                        warn!("Unhandled status code: {status}", status = res.status());
-                    }
-                    debug!("Response: {res:?}", res = &res);
-                }
-                // endregion
-            } else {
-                warn!("not found");
+                info!("Success! {record_id} for {owner} was moved from {from} to {to}.", to = &record.path, record_id = &record_id);
             }
-        }
-    }
-
-    pub async fn get_record(&self, owner_id: UserId, record_id: RecordId) -> Option<Record> {
-        let endpoint = format!("{BASE_POINT}/users/{owner_id}/records/{record_id}", owner_id = &owner_id, record_id = &record_id);
-
-        let mut request = CLIENT
-            .get(endpoint);
-
-        if let Some(authorization_info) = &self.authorization_info {
-            debug!("auth set");
-            request = request.header(AUTHORIZATION, authorization_info.as_authorization_header_value());
+            // endregion
         }
 
-        let res = request
-            .send()
-            .await
-            .expect("HTTP connection error");
+        Ok(())
+    }
 
-        match res.status().as_u16() {
-            200 => {
-                let record = res
-                    .json()
-                    .await
-                    .expect("Failed to parse JSON: This is critical bug. Please open ticket on https://github.com/KisaragiEffective/neosvr-inventory-management/issues.");
+    pub async fn get_record(&self, owner: RecordOwner, record_id: RecordId) -> Result<Record, InventoryError> {
+        self.with_session(|auth| self.backend.get_record(auth, &owner, &record_id)).await
+    }
+}
 
-                Some(record)
-            }
-            403 => {
-                error!("Unauthorized");
-                None
-            }
-            404 => None,
-            other_status => {
-                warn!("Unhandled status code: {other_status}");
-                None
-            }
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use crate::backend::fixture::{owner, path};
+    use crate::backend::InMemoryBackend;
+    use crate::model::{AuthorizationInfo, SessionToken};
+    use super::PreLogin;
+
+    #[tokio::test]
+    async fn extends_session_every_time_it_is_expiring() {
+        let authorization_info = AuthorizationInfo {
+            expire: Some(Utc::now() + TimeDelta::minutes(1)),
+            lifetime: Some(TimeDelta::hours(1)),
+            ..AuthorizationInfo::new("U-test".parse().unwrap(), SessionToken::new("token".to_string()))
+        };
+        let client = PreLogin::from_session_data(InMemoryBackend::from_records(vec![]), None, Some(authorization_info));
+
+        for _ in 0..2 {
+            assert!(client.is_session_expiring());
+            client.get_directory_items(owner("U-test"), path("Inventory")).await.unwrap();
+            assert!(!client.is_session_expiring());
+
+            let expire = client.session_expire.lock().unwrap().unwrap();
+            assert!(expire - Utc::now() > TimeDelta::minutes(59));
+
+            // pretends that the time has passed
+            *client.session_expire.lock().unwrap() = Some(Utc::now() + TimeDelta::minutes(1));
         }
     }
 }
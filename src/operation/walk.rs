@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{debug, warn};
+use serde::Serialize;
+use url::Url;
+use crate::backend::InventoryBackend;
+use crate::error::InventoryError;
+use crate::model::{AbsoluteInventoryPath, Record, RecordLink, RecordOwner, RecordType};
+use super::LoggedIn;
+
+/// A record found by [`LoggedIn::walk`].
+#[derive(Serialize, Debug, Clone)]
+pub struct WalkedRecord {
+    /// `1` for the records directly under the directory where the walk started.
+    pub depth: usize,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    /// Descends into child directories up to this depth. `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Number of directories which are fetched at once.
+    pub concurrency: usize,
+    /// Descends into the directories which are pointed by [`RecordType::Link`], including the ones of other owners.
+    pub follow_links: bool,
+}
+
+/// Directory, which can be in the inventory of other owner if it is reached by a link.
+type Location = (RecordOwner, AbsoluteInventoryPath);
+
+enum Job {
+    /// Fetches the records in the directory. They are at the given depth.
+    List(Location, usize),
+    /// Resolves the link at the given depth.
+    Resolve(Url, usize),
+}
+
+enum Container {
+    Resolved(Location),
+    /// The link which is not resolved yet.
+    Unresolved(Url),
+}
+
+enum Done {
+    List(Location, usize, Vec<Record>),
+    Resolve(Url, usize, Option<Location>),
+}
+
+/// What is known while fetching.
+struct Fetched {
+    options: WalkOptions,
+    children: HashMap<Location, Vec<Record>>,
+    /// The shallowest depth of the records in each visited directory.
+    /// Directories can be reached multiple times by links, and should be descended as deep as the shallowest one allows.
+    visited: HashMap<Location, usize>,
+    link_targets: HashMap<Url, Option<Location>>,
+    jobs: VecDeque<Job>,
+}
+
+impl Fetched {
+    fn new(options: WalkOptions) -> Self {
+        Self {
+            options,
+            children: HashMap::new(),
+            visited: HashMap::new(),
+            link_targets: HashMap::new(),
+            jobs: VecDeque::new(),
+        }
+    }
+
+    fn allows(&self, depth: usize) -> bool {
+        self.options.max_depth.map_or(true, |max_depth| depth <= max_depth)
+    }
+
+    /// Queues what is needed to list the records in `location` at `depth`.
+    fn visit(&mut self, location: Location, depth: usize) {
+        let mut to_visit = vec![(location, depth)];
+
+        while let Some((location, depth)) = to_visit.pop() {
+            // every directory is visited only once, so this terminates even if links form a cycle
+            if !self.allows(depth) || self.visited.get(&location).is_some_and(|visited| *visited <= depth) {
+                continue;
+            }
+            self.visited.insert(location.clone(), depth);
+
+            let Some(records) = self.children.get(&location) else {
+                debug!("descending into {path} of {owner}", owner = &location.0, path = &location.1);
+                self.jobs.push_back(Job::List(location, depth));
+                continue;
+            };
+
+            for record in records {
+                match self.container_of(&location, record) {
+                    Some(Container::Resolved(child)) => to_visit.push((child, depth + 1)),
+                    Some(Container::Unresolved(uri)) => self.jobs.push_back(Job::Resolve(uri, depth)),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Returns where the records in `record` are, if it is a directory or a link to be followed.
+    fn container_of(&self, parent: &Location, record: &Record) -> Option<Container> {
+        match record.record_type {
+            RecordType::Directory => Some(Container::Resolved((parent.0.clone(), parent.1.join(&record.name)))),
+            RecordType::Link if self.options.follow_links => {
+                let uri = record.asset_uri.as_ref()?;
+                self.link_targets.get(uri).map_or_else(
+                    || Some(Container::Unresolved(uri.clone())),
+                    |target| target.clone().map(Container::Resolved),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    fn done(&mut self, done: Done) {
+        match done {
+            Done::List(location, depth, records) => {
+                self.children.insert(location.clone(), records);
+                // visit again to descend into the children, from the shallowest depth which is known until now
+                let depth = self.visited.remove(&location).map_or(depth, |visited| visited.min(depth));
+                self.visit(location, depth);
+            }
+            Done::Resolve(uri, depth, target) => {
+                self.link_targets.insert(uri, target.clone());
+                if let Some(target) = target {
+                    self.visit(target, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+impl<B: InventoryBackend> LoggedIn<B> {
+    /// Lists records under `path`, descending into child directories as `options` tells.
+    /// Records are returned in pre-order, so each directory is followed by its children.
+    pub async fn walk(&self, owner: RecordOwner, path: AbsoluteInventoryPath, options: WalkOptions) -> Result<Vec<WalkedRecord>, InventoryError> {
+        let root = (owner, path);
+        let run = |job: Job| async move {
+            match job {
+                Job::List((owner, path), depth) => {
+                    let records = self.get_directory_items(owner.clone(), path.clone()).await?;
+                    Ok(Done::List((owner, path), depth, records))
+                }
+                Job::Resolve(uri, depth) => {
+                    let target = self.resolve_link(&uri).await;
+                    Ok::<_, InventoryError>(Done::Resolve(uri, depth, target))
+                }
+            }
+        };
+
+        // region fetch
+        let mut fetched = Fetched::new(options);
+        fetched.visit(root.clone(), 1);
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            while in_flight.len() < options.concurrency {
+                let Some(job) = fetched.jobs.pop_front() else { break };
+                in_flight.push(run(job));
+            }
+
+            let Some(done) = in_flight.next().await else { break };
+            fetched.done(done?);
+        }
+        // endregion
+
+        // region arrange in pre-order
+        let to_pending = |location: &Location, depth: usize| {
+            // the next one to be visited comes last
+            fetched.children.get(location)
+                .into_iter()
+                .flatten()
+                .rev()
+                .map(|record| (depth, record))
+                .collect::<Vec<_>>()
+        };
+
+        let mut walked = vec![];
+        let mut pending = to_pending(&root, 1);
+        // `ancestors[d - 1]` is the location of the records at depth `d`
+        let mut ancestors = vec![root];
+        // the same link is reached once per path to it, but warned only once
+        let mut warned = HashSet::new();
+
+        while let Some((depth, record)) = pending.pop() {
+            ancestors.truncate(depth);
+
+            let parent = ancestors.last().expect("root must be there");
+            let container = fetched.container_of(parent, record).filter(|_| fetched.allows(depth + 1));
+            if let Some(Container::Resolved(container)) = container {
+                if ancestors.contains(&container) {
+                    if warned.insert(&record.id) {
+                        warn!("{name} ({id}) links to its ancestor, not following", name = &record.name, id = &record.id);
+                    }
+                } else {
+                    pending.extend(to_pending(&container, depth + 1));
+                    ancestors.push(container);
+                }
+            }
+
+            walked.push(WalkedRecord { depth, record: record.clone() });
+        }
+        // endregion
+
+        Ok(walked)
+    }
+
+    /// Returns the directory which is pointed by the link, or `None` if it can not be followed.
+    /// Failures are not fatal, because the link may be broken or point a private directory of other owner.
+    async fn resolve_link(&self, uri: &Url) -> Option<Location> {
+        let Some(link) = RecordLink::from_uri(uri) else {
+            warn!("{uri} is not a link to record, not following");
+            return None
+        };
+
+        match self.get_record(link.owner.clone(), link.record_id).await {
+            Ok(target) if target.record_type == RecordType::Directory => {
+                let path = AbsoluteInventoryPath::from_record_path(&target.path).join(&target.name);
+                Some((link.owner, path))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("could not follow {uri}: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::fixture::{link, owner, path, record};
+    use crate::backend::InMemoryBackend;
+    use crate::model::{Record, RecordType};
+    use crate::operation::PreLogin;
+    use super::WalkOptions;
+
+    /// Walks `Inventory` of `U-test` following links, and returns `(depth, id)` in the order.
+    /// Every level of concurrency must give the same result.
+    async fn walk(records: Vec<Record>, max_depth: Option<usize>) -> Vec<(usize, String)> {
+        let client = PreLogin::from_session_data(InMemoryBackend::from_records(records), None, None);
+
+        let mut results = vec![];
+        for concurrency in [1, 8] {
+            let walked = client.walk(owner("U-test"), path("Inventory"), WalkOptions { max_depth, concurrency, follow_links: true }).await.unwrap();
+            results.push(walked.into_iter().map(|walked| (walked.depth, walked.record.id.0)).collect::<Vec<_>>());
+        }
+
+        assert_eq!(results[0], results[1]);
+        results.swap_remove(0)
+    }
+
+    fn expected(records: &[(usize, &str)]) -> Vec<(usize, String)> {
+        records.iter().map(|(depth, id)| (*depth, (*id).to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn self_link_is_not_followed() {
+        let walked = walk(vec![
+            record("U-test", "R-a", "Inventory", "A", RecordType::Directory),
+            link("U-test", "R-self", "Inventory\\A", "Self", "neosrec:///U-test/R-a"),
+        ], None).await;
+
+        assert_eq!(walked, expected(&[(1, "R-a"), (2, "R-self")]));
+    }
+
+    #[tokio::test]
+    async fn mutual_links_are_followed_until_ancestor() {
+        let walked = walk(vec![
+            record("U-test", "R-a", "Inventory", "A", RecordType::Directory),
+            record("U-test", "R-b", "Inventory", "B", RecordType::Directory),
+            record("U-test", "R-a1", "Inventory\\A", "a1", RecordType::Object),
+            link("U-test", "R-to-b", "Inventory\\A", "To B", "neosrec:///U-test/R-b"),
+            record("U-test", "R-b1", "Inventory\\B", "b1", RecordType::Object),
+            link("U-test", "R-to-a", "Inventory\\B", "To A", "neosrec:///U-test/R-a"),
+        ], None).await;
+
+        assert_eq!(walked, expected(&[
+            (1, "R-a"),
+            (2, "R-a1"),
+            (2, "R-to-b"),
+            (3, "R-b1"),
+            (3, "R-to-a"),
+            (1, "R-b"),
+            (2, "R-b1"),
+            (2, "R-to-a"),
+            (3, "R-a1"),
+            (3, "R-to-b"),
+        ]));
+    }
+
+    #[tokio::test]
+    async fn max_depth_is_counted_along_links() {
+        // `E` is at depth 2 directly, but at depth 1 through the link. Its descendants have to be
+        // fetched as deep as the latter allows.
+        let walked = walk(vec![
+            record("U-test", "R-d", "Inventory", "D", RecordType::Directory),
+            link("U-test", "R-to-e", "Inventory", "To E", "neosrec:///U-test/R-e"),
+            record("U-test", "R-e", "Inventory\\D", "E", RecordType::Directory),
+            record("U-test", "R-x", "Inventory\\D\\E", "x", RecordType::Object),
+            record("U-test", "R-f", "Inventory\\D\\E", "F", RecordType::Directory),
+            record("U-test", "R-y", "Inventory\\D\\E\\F", "y", RecordType::Object),
+        ], Some(3)).await;
+
+        assert_eq!(walked, expected(&[
+            (1, "R-d"),
+            (2, "R-e"),
+            (3, "R-x"),
+            (3, "R-f"),
+            (1, "R-to-e"),
+            (2, "R-x"),
+            (2, "R-f"),
+            (3, "R-y"),
+        ]));
+    }
+}
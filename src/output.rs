@@ -0,0 +1,235 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use anyhow::bail;
+use serde::Serialize;
+use serde_json::Value;
+use strum::{Display, EnumString};
+use unicode_width::UnicodeWidthStr;
+
+/// How `list` and `metadata` print the results.
+#[derive(EnumString, Display, Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum OutputFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// Pretty-printed JSON array.
+    Json,
+    Csv,
+    Tsv,
+    Yaml,
+    /// Aligned columns for human.
+    Table,
+}
+
+/// Fields selected by `--fields`, in the order of output.
+///
+/// Each field can be written either in the name of the Rust field (`record_type`) or the serialized key (`recordType`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldSelection(Vec<String>);
+
+/// Fields of [`crate::model::Record`] which are serialized in the name that can not be derived from the Rust one.
+const RENAMED_FIELDS: [(&str, &str); 4] = [
+    ("created_at", "creationTime"),
+    ("updated_at", "lastModificationTime"),
+    ("last_update_by", "lastModifyingUserId"),
+    ("last_update_machine", "lastModifyingMachineId"),
+];
+
+/// Fields of [`crate::operation::WalkedRecord`], which `list` and `find` print, in the name of the Rust fields.
+const FIELDS: [&str; 24] = [
+    "depth", "id", "asset_uri", "global_version", "local_version", "last_update_by", "last_update_machine", "name",
+    "record_type", "owner_name", "tags", "path", "is_public", "is_for_patrons", "is_listed", "is_deleted", "thumbnail_uri",
+    "created_at", "updated_at", "random_order", "visits", "rating", "owner_id", "submissions",
+];
+
+impl FieldSelection {
+    fn to_serialized_key(field: &str) -> String {
+        if let Some((_, key)) = RENAMED_FIELDS.iter().find(|(name, _)| *name == field) {
+            return (*key).to_string()
+        }
+
+        let mut key = String::with_capacity(field.len());
+        let mut upper = false;
+        for c in field.chars() {
+            if c == '_' {
+                upper = true;
+            } else if upper {
+                key.extend(c.to_uppercase());
+                upper = false;
+            } else {
+                key.push(c);
+            }
+        }
+
+        key
+    }
+
+    fn project(&self, value: Value) -> Value {
+        let Value::Object(mut object) = value else {
+            return value
+        };
+
+        Value::Object(self.0.iter().map(|key| (key.clone(), object.remove(key).unwrap_or(Value::Null))).collect())
+    }
+}
+
+impl FromStr for FieldSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields.iter().any(|field| field.is_empty()) {
+            bail!("field name must not be empty");
+        }
+
+        // typo would otherwise silently result in empty column
+        let keys = fields.into_iter().map(Self::to_serialized_key).collect::<Vec<_>>();
+        if let Some(unknown) = keys.iter().find(|key| !FIELDS.iter().any(|field| Self::to_serialized_key(field) == **key)) {
+            bail!("unknown field: {unknown}. Available fields are {fields}", fields = FIELDS.join(", "));
+        }
+
+        Ok(Self(keys))
+    }
+}
+
+/// Writes `items` to `out` in `format`. If `fields` is given, only those fields are written.
+///
+/// For tabular formats, each top-level field becomes a column. Columns are ordered as they first appear,
+/// and nested values such as arrays are written in JSON.
+pub fn write_items<T: Serialize, W: Write>(out: &mut W, items: &[T], format: OutputFormat, fields: Option<&FieldSelection>) -> io::Result<()> {
+    let mut values = items.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+
+    if let Some(fields) = fields {
+        values = values.into_iter().map(|value| fields.project(value)).collect();
+    }
+
+    match format {
+        OutputFormat::Jsonl => {
+            for value in &values {
+                serde_json::to_writer(&mut *out, value)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &values)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Yaml => serde_yaml_ng::to_writer(&mut *out, &values).map_err(io::Error::other)?,
+        OutputFormat::Csv => write_delimited(out, &values, b',')?,
+        OutputFormat::Tsv => write_delimited(out, &values, b'\t')?,
+        OutputFormat::Table => write_table(out, &values)?,
+    }
+
+    out.flush()
+}
+
+fn write_delimited<W: Write>(out: &mut W, values: &[Value], delimiter: u8) -> io::Result<()> {
+    let (header, rows) = to_rows(values);
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(out);
+
+    writer.write_record(&header)?;
+    for row in rows {
+        writer.write_record(&row)?;
+    }
+
+    writer.flush()
+}
+
+fn write_table<W: Write>(out: &mut W, values: &[Value]) -> io::Result<()> {
+    let (header, rows) = to_rows(values);
+    // tab and newline would break the alignment
+    let rows = rows
+        .into_iter()
+        .map(|row| row.into_iter().map(|cell| cell.replace(['\t', '\n', '\r'], " ")).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].width())
+                .chain(std::iter::once(column.width()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            line.push_str(cell);
+            line.extend(std::iter::repeat(' ').take(width - cell.width() + 2));
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+/// Returns the header and the cells of each value.
+fn to_rows(values: &[Value]) -> (Vec<String>, Vec<Vec<String>>) {
+    let objects = values
+        .iter()
+        .map(|value| match value {
+            Value::Object(object) => object.clone(),
+            other => std::iter::once(("value".to_string(), other.clone())).collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut header: Vec<String> = vec![];
+    for key in objects.iter().flat_map(serde_json::Map::keys) {
+        if !header.contains(key) {
+            header.push(key.clone());
+        }
+    }
+
+    let rows = objects
+        .iter()
+        .map(|object| header.iter().map(|key| object.get(key).map(to_cell).unwrap_or_default()).collect())
+        .collect();
+
+    (header, rows)
+}
+
+fn to_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::fixture::record;
+    use crate::model::RecordType;
+    use crate::operation::WalkedRecord;
+    use super::{FieldSelection, FIELDS};
+
+    #[test]
+    fn fields_cover_walked_record() {
+        let walked = WalkedRecord { depth: 1, record: record("U-test", "R-chair", "Inventory", "Chair", RecordType::Object) };
+        let serde_json::Value::Object(object) = serde_json::to_value(walked).unwrap() else {
+            panic!("record must be serialized as object")
+        };
+
+        let mut keys = object.keys().cloned().collect::<Vec<_>>();
+        let mut fields = FIELDS.map(FieldSelection::to_serialized_key).to_vec();
+        keys.sort();
+        fields.sort();
+        assert_eq!(keys, fields);
+    }
+
+    #[test]
+    fn accepts_both_rust_and_serialized_names() {
+        let fields = "name, record_type,lastModifyingUserId,depth".parse::<FieldSelection>().unwrap();
+
+        assert_eq!(fields, FieldSelection(vec!["name".to_string(), "recordType".to_string(), "lastModifyingUserId".to_string(), "depth".to_string()]));
+    }
+
+    #[test]
+    fn rejects_unknown_field() {
+        assert!("name,nmae".parse::<FieldSelection>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use crate::cli::Platform;
+use crate::config::config_directory;
+use crate::model::{generate_machine_id, AuthorizationInfo, Secret, SessionToken, UserId};
+
+/// Session which is saved by `login` subcommand, and reused by later invocations.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredSession {
+    pub platform: Platform,
+    /// The session is only valid for the server that issued it.
+    pub api_base: String,
+    pub user_id: UserId,
+    pub token: SessionToken,
+    /// `None` if the server did not tell it, or the session is saved by older version.
+    #[serde(default)]
+    pub expire: Option<DateTime<Utc>>,
+    /// Lifetime of the session in seconds, which is used to estimate the expiry after extension.
+    #[serde(default)]
+    pub lifetime_seconds: Option<i64>,
+}
+
+impl StoredSession {
+    pub fn new(platform: Platform, api_base: String, authorization_info: AuthorizationInfo) -> Self {
+        Self {
+            platform,
+            api_base,
+            user_id: authorization_info.owner_id,
+            token: authorization_info.token,
+            expire: authorization_info.expire,
+            lifetime_seconds: authorization_info.lifetime.map(|lifetime| lifetime.num_seconds()),
+        }
+    }
+
+    pub fn to_authorization_info(&self) -> AuthorizationInfo {
+        AuthorizationInfo {
+            expire: self.expire,
+            lifetime: self.lifetime_seconds.map(TimeDelta::seconds),
+            ..AuthorizationInfo::new(self.user_id.clone(), self.token.clone())
+        }
+    }
+
+    /// Returns `true` if this session can be used to talk with `api_base` on `platform`.
+    pub fn is_for(&self, platform: Platform, api_base: &str) -> bool {
+        self.platform == platform && self.api_base == api_base
+    }
+
+    /// Returns `None` if no session is stored.
+    pub fn load() -> io::Result<Option<Self>> {
+        let content = match fs::read_to_string(Self::path()?) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Overwrites the stored session. The file is readable only from the current user.
+    pub fn save(&self) -> io::Result<()> {
+        write_private(&Self::path()?, serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// Does nothing if no session is stored.
+    pub fn delete() -> io::Result<()> {
+        match fs::remove_file(Self::path()?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn path() -> io::Result<PathBuf> {
+        private_file("session.json")
+    }
+}
+
+/// Returns the machine id of this installation, creating it on first use.
+/// If it can not be persisted, a fresh one is used for this time.
+pub fn machine_id() -> Secret<String> {
+    let load = || -> io::Result<String> {
+        let path = private_file("machine-id")?;
+        match fs::read_to_string(&path) {
+            Ok(machine_id) if !machine_id.trim().is_empty() => Ok(machine_id.trim().to_string()),
+            Ok(_) => Err(io::Error::new(ErrorKind::InvalidData, "machine id is empty")),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let machine_id = generate_machine_id();
+                write_private(&path, machine_id.as_bytes())?;
+                debug!("machine id is created");
+                Ok(machine_id)
+            }
+            Err(e) => Err(e),
+        }
+    };
+
+    let machine_id = load().unwrap_or_else(|e| {
+        warn!("failed to persist machine id, using temporary one: {e}");
+        generate_machine_id()
+    });
+
+    Secret::new(machine_id)
+}
+
+fn private_file(name: &str) -> io::Result<PathBuf> {
+    config_directory()
+        .map(|dir| dir.join(name))
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "could not determine config directory"))
+}
+
+/// Overwrites `path` with `content`. The file is readable only from the current user.
+fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    // mode is only applied when the file is created
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(content)
+}
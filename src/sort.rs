@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+use std::env;
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use log::{debug, warn};
+use strum::{Display, EnumString};
+use crate::model::{Record, RecordType};
+use crate::operation::WalkedRecord;
+
+#[derive(EnumString, Display, Copy, Clone, Eq, PartialEq, Debug)]
+#[strum(serialize_all = "snake_case", ascii_case_insensitive)]
+pub enum SortKey {
+    Name,
+    Type,
+    Created,
+    Modified,
+    RandomOrder,
+    Visits,
+    Rating,
+}
+
+/// How records in the same directory are ordered.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SortOptions {
+    /// `name`, `type`, `created`, `modified`, `random_order`, `visits` or `rating`. Keeps the order from the server if omitted.
+    #[clap(long)]
+    sort: Option<SortKey>,
+    /// Sorts in descending order. Directories are still put first.
+    #[clap(long, requires = "sort")]
+    reverse: bool,
+    /// Does not put directories before other records.
+    #[clap(long)]
+    no_directories_first: bool,
+}
+
+impl SortOptions {
+    /// Sorts records in each directory, keeping the children right after their directory.
+    /// `records` must be in pre-order, such as the one which is returned by [`crate::operation::LoggedIn::walk`].
+    pub fn sort(&self, records: Vec<WalkedRecord>) -> Vec<WalkedRecord> {
+        if self.sort.is_none() && self.no_directories_first {
+            return records
+        }
+
+        let collator = (self.sort == Some(SortKey::Name)).then(name_collator).flatten();
+        let compare = |a: &Record, b: &Record| {
+            let directories_first = if self.no_directories_first {
+                Ordering::Equal
+            } else {
+                (b.record_type == RecordType::Directory).cmp(&(a.record_type == RecordType::Directory))
+            };
+
+            let by_key = match self.sort {
+                None => Ordering::Equal,
+                Some(SortKey::Name) => {
+                    let (a, b) = (a.plain_name(), b.plain_name());
+                    collator.as_ref().map_or_else(|| a.cmp(&b), |collator| collator.compare(&a, &b))
+                }
+                Some(SortKey::Type) => a.record_type.cmp(&b.record_type),
+                Some(SortKey::Created) => a.created_at.cmp(&b.created_at),
+                Some(SortKey::Modified) => a.updated_at.cmp(&b.updated_at),
+                Some(SortKey::RandomOrder) => a.random_order.cmp(&b.random_order),
+                Some(SortKey::Visits) => a.visits.cmp(&b.visits),
+                Some(SortKey::Rating) => a.rating.total_cmp(&b.rating),
+            };
+
+            directories_first.then(if self.reverse { by_key.reverse() } else { by_key })
+        };
+
+        sort_siblings(records, 1, &compare)
+    }
+}
+
+fn sort_siblings(records: Vec<WalkedRecord>, depth: usize, compare: &impl Fn(&Record, &Record) -> Ordering) -> Vec<WalkedRecord> {
+    // each record at `depth`, and its descendants
+    let mut families: Vec<(WalkedRecord, Vec<WalkedRecord>)> = vec![];
+    for walked in records {
+        match families.last_mut() {
+            Some((_, descendants)) if walked.depth > depth => descendants.push(walked),
+            _ => families.push((walked, vec![])),
+        }
+    }
+
+    // stable, so that records which are compared as equal keep the order from the server
+    families.sort_by(|(a, _), (b, _)| compare(&a.record, &b.record));
+
+    families
+        .into_iter()
+        .flat_map(|(walked, descendants)| std::iter::once(walked).chain(sort_siblings(descendants, depth + 1, compare)))
+        .collect()
+}
+
+/// Collator for the locale of the environment, such as `LANG=ja_JP.UTF-8`.
+/// Falls back to the root collation, which is still better than comparing code points.
+fn name_collator() -> Option<Collator> {
+    let locale = ["LC_ALL", "LC_COLLATE", "LANG"]
+        .into_iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .and_then(|value| {
+            // ja_JP.UTF-8 -> ja-JP
+            let language_tag = value.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+            language_tag.parse::<Locale>().ok()
+        })
+        .unwrap_or(Locale::UND);
+    debug!("collating names in {locale}");
+
+    Collator::try_new(&(&locale).into(), CollatorOptions::new())
+        .inspect_err(|e| warn!("failed to load collation for {locale}, comparing by code points: {e}"))
+        .ok()
+}
@@ -0,0 +1,232 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use strum::{Display, EnumString};
+use crate::filter::RecordFilter;
+use crate::model::{AbsoluteInventoryPath, RecordId, RecordType};
+use crate::operation::WalkedRecord;
+use crate::output::{write_items, OutputFormat};
+
+/// How `stats` prints the report.
+#[derive(EnumString, Display, Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeCounts {
+    pub directory: usize,
+    pub object: usize,
+    pub texture: usize,
+    pub audio: usize,
+    pub link: usize,
+}
+
+impl TypeCounts {
+    fn count(&mut self, record_type: RecordType) {
+        match record_type {
+            RecordType::Directory => self.directory += 1,
+            RecordType::Object => self.object += 1,
+            RecordType::Texture => self.texture += 1,
+            RecordType::Audio => self.audio += 1,
+            RecordType::Link => self.link += 1,
+        }
+    }
+
+    pub const fn total(&self) -> usize {
+        self.directory + self.object + self.texture + self.audio + self.link
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryStats {
+    pub path: String,
+    /// Records directly under this directory.
+    pub direct: TypeCounts,
+    /// Records under this directory, including the ones in its descendants.
+    pub deep: TypeCounts,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UntouchedRecord {
+    pub id: RecordId,
+    pub path: String,
+    pub name: String,
+    pub record_type: RecordType,
+    #[serde(rename = "lastModificationTime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Composition of the records under a directory. Directories are always walked into,
+/// but only the records which match the filter are counted.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryStats {
+    /// The walked directory comes first, followed by its descendants in pre-order.
+    pub directories: Vec<DirectoryStats>,
+    /// Descendant directories which have the most records deeply.
+    pub largest_directories: Vec<DirectoryStats>,
+    /// Records other than directories, which are modified least recently.
+    pub oldest_records: Vec<UntouchedRecord>,
+    /// Number of records other than directories.
+    pub items: usize,
+    pub public_items: usize,
+    pub listed_items: usize,
+    /// `public_items / items`, or `0` if there is no item.
+    pub public_share: f64,
+    /// `listed_items / items`, or `0` if there is no item.
+    pub listed_share: f64,
+}
+
+impl InventoryStats {
+    /// `records` must be in pre-order, such as the one which is returned by [`crate::operation::LoggedIn::walk`].
+    /// `top` limits the length of [`Self::largest_directories`] and [`Self::oldest_records`].
+    pub fn compute(root: &AbsoluteInventoryPath, records: &[WalkedRecord], filter: &RecordFilter, top: usize) -> Self {
+        let new_stats = |path: &AbsoluteInventoryPath| DirectoryStats {
+            path: path.to_absolute_path(),
+            direct: TypeCounts::default(),
+            deep: TypeCounts::default(),
+        };
+        let mut directories = vec![new_stats(root)];
+        // same indices as `directories`
+        let mut paths = vec![root.clone()];
+        // index in `directories` of the ancestors, where `ancestors[d - 1]` is the parent of the record at depth `d`
+        let mut ancestors = vec![0];
+        let mut index_by_path = HashMap::new();
+        let mut oldest_records = vec![];
+        let (mut items, mut public_items, mut listed_items) = (0, 0, 0);
+
+        for walked in records {
+            let record = &walked.record;
+            ancestors.truncate(walked.depth);
+
+            if filter.matches(record) {
+                let parent = *ancestors.last().expect("root must be there");
+                directories[parent].direct.count(record.record_type);
+                for &ancestor in &ancestors {
+                    directories[ancestor].deep.count(record.record_type);
+                }
+
+                if record.record_type != RecordType::Directory {
+                    items += 1;
+                    public_items += usize::from(record.is_public);
+                    listed_items += usize::from(record.is_listed);
+                    oldest_records.push(UntouchedRecord {
+                        id: record.id.clone(),
+                        path: AbsoluteInventoryPath::from_record_path(&record.path).to_absolute_path(),
+                        name: record.name.clone(),
+                        record_type: record.record_type,
+                        updated_at: record.updated_at,
+                    });
+                }
+            }
+
+            if record.record_type == RecordType::Directory {
+                let path = paths[*ancestors.last().expect("root must be there")].join(&record.name);
+                // directories of the same name are merged, as they can not be distinguished by path
+                let index = *index_by_path.entry(path.clone()).or_insert_with(|| {
+                    directories.push(new_stats(&path));
+                    paths.push(path);
+                    directories.len() - 1
+                });
+                ancestors.push(index);
+            }
+        }
+
+        let mut largest_directories = directories[1..].to_vec();
+        largest_directories.sort_by_key(|directory| Reverse(directory.deep.total()));
+        largest_directories.truncate(top);
+
+        oldest_records.sort_by_key(|record| record.updated_at);
+        oldest_records.truncate(top);
+
+        let share = |count: usize| {
+            if items == 0 {
+                0.0
+            } else {
+                #[allow(clippy::cast_precision_loss)]
+                let share = count as f64 / items as f64;
+                share
+            }
+        };
+
+        Self {
+            directories,
+            largest_directories,
+            oldest_records,
+            items,
+            public_items,
+            listed_items,
+            public_share: share(public_items),
+            listed_share: share(listed_items),
+        }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W, format: ReportFormat) -> io::Result<()> {
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+                out.flush()
+            }
+            ReportFormat::Table => self.write_table(out),
+        }
+    }
+
+    fn write_table<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "{items} items, {public_items} public ({public:.1}%), {listed_items} listed ({listed:.1}%)",
+            items = self.items,
+            public_items = self.public_items,
+            public = self.public_share * 100.0,
+            listed_items = self.listed_items,
+            listed = self.listed_share * 100.0,
+        )?;
+
+        writeln!(out, "\n# Directories (deep total, direct in parentheses)")?;
+        write_items(out, &Self::directory_rows(&self.directories), OutputFormat::Table, None)?;
+
+        writeln!(out, "\n# Largest directories")?;
+        write_items(out, &Self::directory_rows(&self.largest_directories), OutputFormat::Table, None)?;
+
+        writeln!(out, "\n# Oldest records")?;
+        let oldest = self.oldest_records
+            .iter()
+            .map(|record| json!({
+                "lastModificationTime": record.updated_at,
+                "recordType": record.record_type,
+                "path": record.path,
+                "name": record.name,
+                "id": record.id,
+            }))
+            .collect::<Vec<_>>();
+        write_items(out, &oldest, OutputFormat::Table, None)
+    }
+
+    fn directory_rows(directories: &[DirectoryStats]) -> Vec<serde_json::Value> {
+        let cell = |deep: usize, direct: usize| format!("{deep} ({direct})");
+
+        directories
+            .iter()
+            .map(|directory| json!({
+                "path": directory.path,
+                "total": cell(directory.deep.total(), directory.direct.total()),
+                "directory": cell(directory.deep.directory, directory.direct.directory),
+                "object": cell(directory.deep.object, directory.direct.object),
+                "texture": cell(directory.deep.texture, directory.direct.texture),
+                "audio": cell(directory.deep.audio, directory.direct.audio),
+                "link": cell(directory.deep.link, directory.direct.link),
+            }))
+            .collect()
+    }
+}
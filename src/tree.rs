@@ -0,0 +1,111 @@
+use std::fmt::{Display, Formatter};
+use crate::model::{AbsoluteInventoryPath, RecordType};
+use crate::operation::WalkedRecord;
+
+/// Renders the result of [`crate::operation::LoggedIn::walk`] like the Unix `tree` command.
+pub struct TreeView<'a> {
+    root: &'a AbsoluteInventoryPath,
+    records: &'a [WalkedRecord],
+    max_depth: Option<usize>,
+    colored: bool,
+}
+
+struct Line<'a> {
+    walked: &'a WalkedRecord,
+    is_last_sibling: bool,
+    /// `None` if this is not a directory, or its children were not fetched.
+    child_count: Option<usize>,
+}
+
+impl<'a> TreeView<'a> {
+    /// `records` must be in pre-order, and `max_depth` must be the one which was used to walk.
+    pub const fn new(root: &'a AbsoluteInventoryPath, records: &'a [WalkedRecord], max_depth: Option<usize>, colored: bool) -> Self {
+        Self { root, records, max_depth, colored }
+    }
+
+    /// Links are expanded only if they are followed, which is told by the presence of children.
+    fn is_expanded(&self, walked: &WalkedRecord, child_count: usize) -> bool {
+        match walked.record.record_type {
+            RecordType::Directory => self.max_depth.map_or(true, |max_depth| walked.depth < max_depth),
+            RecordType::Link => child_count > 0,
+            _ => false,
+        }
+    }
+
+    /// Returns lines in the same order as `records`, and the number of records directly under the root.
+    fn lines(&self) -> (Vec<Line<'a>>, usize) {
+        // scanning backwards, `seen[d]` is the number of records at depth `d` which share the parent
+        // with the current one, and come after it.
+        let mut seen = vec![0_usize; 2];
+        let mut lines = Vec::with_capacity(self.records.len());
+
+        for walked in self.records.iter().rev() {
+            let depth = walked.depth;
+            if seen.len() <= depth + 1 {
+                seen.resize(depth + 2, 0);
+            }
+
+            let child_count = self.is_expanded(walked, seen[depth + 1]).then_some(seen[depth + 1]);
+            // the records after this one at deeper level are descendant of this one, not of its siblings
+            seen[depth + 1..].fill(0);
+
+            lines.push(Line { walked, is_last_sibling: seen[depth] == 0, child_count });
+            seen[depth] += 1;
+        }
+
+        lines.reverse();
+        (lines, seen[1])
+    }
+
+    fn write_name(&self, f: &mut Formatter<'_>, record_type: RecordType, name: &str) -> std::fmt::Result {
+        if !self.colored {
+            return f.write_str(name)
+        }
+
+        let color = match record_type {
+            RecordType::Directory => "1;34",
+            RecordType::Object => "32",
+            RecordType::Texture => "35",
+            RecordType::Audio => "33",
+            RecordType::Link => "36",
+        };
+
+        write!(f, "\x1b[{color}m{name}\x1b[0m")
+    }
+}
+
+impl Display for TreeView<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (lines, root_count) = self.lines();
+
+        let root = self.root.to_absolute_path();
+        self.write_name(f, RecordType::Directory, if root.is_empty() { "/" } else { &root })?;
+        writeln!(f, " ({root_count} items)")?;
+
+        // whether the ancestor at each depth is the last one in its siblings
+        let mut ancestors: Vec<bool> = vec![];
+        let mut directories = 0;
+        for line in &lines {
+            ancestors.truncate(line.walked.depth - 1);
+            for &is_last in &ancestors {
+                f.write_str(if is_last { "    " } else { "│   " })?;
+            }
+            f.write_str(if line.is_last_sibling { "└── " } else { "├── " })?;
+
+            let record = &line.walked.record;
+            self.write_name(f, record.record_type, &record.name)?;
+            if let Some(child_count) = line.child_count {
+                write!(f, " ({child_count} items)")?;
+            }
+            writeln!(f)?;
+
+            if record.record_type == RecordType::Directory {
+                directories += 1;
+            }
+            ancestors.push(line.is_last_sibling);
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{directories} directories, {records} records", records = lines.len() - directories)
+    }
+}